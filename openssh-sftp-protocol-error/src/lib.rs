@@ -1,5 +1,14 @@
 mod unix_timestamp_error;
 pub use unix_timestamp_error::UnixTimeStampError;
 
+mod permissions_error;
+pub use permissions_error::ParsePermissionsError;
+
+mod pending_request_error;
+pub use pending_request_error::PendingRequestError;
+
+mod request_error;
+pub use request_error::RequestError;
+
 mod response_error;
 pub use response_error::{ErrMsg, ErrorCode};
@@ -0,0 +1,13 @@
+use thiserror::Error as ThisError;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ThisError)]
+#[non_exhaustive]
+pub enum PendingRequestError {
+    /// The id is already used by another request in flight.
+    #[error("Request id {0} is already pending.")]
+    DuplicateRequestId(u32),
+
+    /// Received a response that does not match any request in flight.
+    #[error("Response id {0} does not match any pending request.")]
+    UnknownResponseId(u32),
+}
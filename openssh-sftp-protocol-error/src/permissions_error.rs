@@ -0,0 +1,17 @@
+use thiserror::Error as ThisError;
+
+#[derive(Debug, Clone, Eq, PartialEq, ThisError)]
+#[non_exhaustive]
+pub enum ParsePermissionsError {
+    /// No octal digit is found.
+    #[error("Permissions string is empty.")]
+    Empty,
+
+    /// Contains character that is not an octal digit.
+    #[error("Permissions string contains non-octal digit {0:?}.")]
+    InvalidDigit(char),
+
+    /// Contains bits other than the permission and special bits (`0o7777`).
+    #[error("Permissions {0:#o} is out of range.")]
+    OutOfRange(u32),
+}
@@ -0,0 +1,13 @@
+use thiserror::Error as ThisError;
+
+#[derive(Debug, Clone, Eq, PartialEq, ThisError)]
+#[non_exhaustive]
+pub enum RequestError {
+    /// Path contains NUL byte, which would be rejected or truncated by
+    /// the server.
+    ///
+    /// Only the length of the path and the offset of the first NUL byte
+    /// are stored, so that the path does not end up in logs.
+    #[error("Path of {len} bytes contains interior NUL byte at offset {nul_offset}.")]
+    PathContainsNul { len: usize, nul_offset: usize },
+}
@@ -1,23 +1,27 @@
-use std::fmt;
+use std::{fmt, io};
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use thiserror::Error as ThisError;
 use vec_strings::TwoStrs;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, ThisError)]
 #[non_exhaustive]
 pub enum ErrorCode {
     /// is returned when a reference is made to a file which should exist
     /// but doesn't.
+    #[error("no such file")]
     NoSuchFile,
 
     /// Returned when the authenticated user does not have sufficient
     /// permissions to perform the operation.
+    #[error("permission denied")]
     PermDenied,
 
     /// A generic catch-all error message.
     ///
     /// It should be returned if an error occurs for which there is no more
     /// specific error code defined.
+    #[error("failure")]
     Failure,
 
     /// May be returned if a badly formatted packet or protocol
@@ -25,20 +29,75 @@ pub enum ErrorCode {
     ///
     /// If the handle is opened read only, but write flag is required,
     /// then `BadMessage` might be returned, vice versa.
+    #[error("bad message")]
     BadMessage,
 
     /// Indicates that an attempt was made to perform an operation which
     /// is not supported for the server.
+    #[error("operation unsupported")]
     OpUnsupported,
 
-    /// Unknown error code
-    Unknown,
+    /// The handle value was invalid.
+    #[error("invalid handle")]
+    InvalidHandle,
+
+    /// The file path does not exist or is invalid.
+    #[error("no such path")]
+    NoSuchPath,
+
+    /// The file already exists.
+    #[error("file already exists")]
+    FileAlreadyExists,
+
+    /// The file is on read-only media, or the media is write protected.
+    #[error("write protected")]
+    WriteProtect,
+
+    /// The operation cannot be completed because the filesystem is full.
+    #[error("no space on filesystem")]
+    NoSpaceOnFilesystem,
+
+    /// The operation cannot be completed because the user has exceeded
+    /// the storage quota.
+    #[error("quota exceeded")]
+    QuotaExceeded,
+
+    /// Unknown error code, with the raw code sent by the server.
+    #[error("unknown error code {0}")]
+    Unknown(u32),
+}
+
+impl From<ErrorCode> for io::ErrorKind {
+    fn from(err_code: ErrorCode) -> Self {
+        match err_code {
+            ErrorCode::NoSuchFile | ErrorCode::NoSuchPath => io::ErrorKind::NotFound,
+            ErrorCode::PermDenied | ErrorCode::WriteProtect => io::ErrorKind::PermissionDenied,
+            ErrorCode::OpUnsupported => io::ErrorKind::Unsupported,
+            ErrorCode::InvalidHandle => io::ErrorKind::InvalidInput,
+            ErrorCode::FileAlreadyExists => io::ErrorKind::AlreadyExists,
+            _ => io::ErrorKind::Other,
+        }
+    }
 }
 
-#[derive(Clone, Deserialize)]
+/// Format: (err_message, language_tag)
+#[derive(Clone, Deserialize, Serialize)]
 pub struct ErrMsg(TwoStrs);
 
+impl Default for ErrMsg {
+    /// Empty err message and language tag.
+    fn default() -> Self {
+        Self::new("", "")
+    }
+}
+
 impl ErrMsg {
+    /// * `err_msg` - must not contain null byte.
+    /// * `language_tag` - must not contain null byte.
+    pub fn new(err_msg: &str, language_tag: &str) -> Self {
+        Self(TwoStrs::new(err_msg, language_tag))
+    }
+
     /// Returns (err_message, language_tag).
     ///
     /// Language tag is defined according to specification [RFC-1766].
@@ -5,8 +5,8 @@ use thiserror::Error as ThisError;
 #[derive(Debug, ThisError)]
 #[non_exhaustive]
 pub enum UnixTimeStampError {
-    /// TimeStamp is earlier than 1970-01-01 00:00:00 UTC.
-    #[error("TimeStamp is earlier than 1970-01-01 00:00:00 UTC.")]
+    /// TimeStamp is too early to be represented using i32 in seconds.
+    #[error("TimeStamp is too early to be represented using i32 in seconds.")]
     TooEarly(#[from] SystemTimeError),
 
     /// TimeStamp is too large to be represented using u32 in seconds.
@@ -64,6 +64,14 @@ def_u32_constants!(SSH_FX_NO_CONNECTION, 6);
 def_u32_constants!(SSH_FX_CONNECTION_LOST, 7);
 def_u32_constants!(SSH_FX_OP_UNSUPPORTED, 8);
 
+// status code defined in sftp v4 and later
+def_u32_constants!(SSH_FX_INVALID_HANDLE, 9);
+def_u32_constants!(SSH_FX_NO_SUCH_PATH, 10);
+def_u32_constants!(SSH_FX_FILE_ALREADY_EXISTS, 11);
+def_u32_constants!(SSH_FX_WRITE_PROTECT, 12);
+def_u32_constants!(SSH_FX_NO_SPACE_ON_FILESYSTEM, 14);
+def_u32_constants!(SSH_FX_QUOTA_EXCEEDED, 15);
+
 // attributes
 def_u32_constants!(SSH_FILEXFER_ATTR_SIZE, 0x00000001);
 def_u32_constants!(SSH_FILEXFER_ATTR_UIDGID, 0x00000002);
@@ -79,6 +87,11 @@ def_u32_constants!(SSH_FXF_CREAT, 0x00000008);
 def_u32_constants!(SSH_FXF_TRUNC, 0x00000010);
 def_u32_constants!(SSH_FXF_EXCL, 0x00000020);
 
+// rename flags (v5+)
+def_u32_constants!(SSH_FXF_RENAME_OVERWRITE, 0x00000001);
+def_u32_constants!(SSH_FXF_RENAME_ATOMIC, 0x00000002);
+def_u32_constants!(SSH_FXF_RENAME_NATIVE, 0x00000004);
+
 // extensions
 def_u32_constants!(SFTP_EXT_POSIX_RENAME, 0x00000001);
 def_u32_constants!(SFTP_EXT_STATVFS, 0x00000002);
@@ -99,3 +112,10 @@ def_ext_constants!(EXT_NAME_LSETSTAT, "lsetstat@openssh.com", 1);
 def_ext_constants!(EXT_NAME_LIMITS, "limits@openssh.com", 1);
 def_ext_constants!(EXT_NAME_EXPAND_PATH, "expand-path@openssh.com", 1);
 def_ext_constants!(EXT_NAME_COPY_DATA, "copy-data", 1);
+def_ext_constants!(EXT_NAME_HOME_DIRECTORY, "home-directory", 1);
+def_ext_constants!(EXT_NAME_CHECK_FILE_HANDLE, "check-file-handle", 1);
+def_ext_constants!(
+    EXT_NAME_USERS_GROUPS_BY_ID,
+    "users-groups-by-id@openssh.com",
+    1
+);
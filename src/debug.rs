@@ -0,0 +1,51 @@
+#![forbid(unsafe_code)]
+
+use std::{fmt, path::Path};
+
+/// Format the path, or only its length if feature `redact-debug` is enabled.
+pub(crate) struct DebugPath<'a>(pub(crate) &'a Path);
+
+impl fmt::Debug for DebugPath<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if cfg!(feature = "redact-debug") {
+            Redacted(self.0.as_os_str().len()).fmt(f)
+        } else {
+            self.0.fmt(f)
+        }
+    }
+}
+
+/// Format the bytes, or only its length if feature `redact-debug` is enabled.
+pub(crate) struct DebugBytes<'a>(pub(crate) &'a [u8]);
+
+impl fmt::Debug for DebugBytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if cfg!(feature = "redact-debug") {
+            Redacted(self.0.len()).fmt(f)
+        } else {
+            self.0.fmt(f)
+        }
+    }
+}
+
+/// Format the string, or only its length if feature `redact-debug` is
+/// enabled.
+pub(crate) struct DebugStr<'a>(pub(crate) &'a str);
+
+impl fmt::Debug for DebugStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if cfg!(feature = "redact-debug") {
+            Redacted(self.0.len()).fmt(f)
+        } else {
+            self.0.fmt(f)
+        }
+    }
+}
+
+struct Redacted(usize);
+
+impl fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<redacted {} bytes>", self.0)
+    }
+}
@@ -0,0 +1,170 @@
+#![forbid(unsafe_code)]
+
+use vec_strings::Strings;
+
+/// Extension pairs of name and data, e.g. as advertised in
+/// [`crate::request::Hello`].
+///
+/// Unlike [`crate::response::Extensions`], this can hold arbitrary extensions.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash)]
+pub struct Extensions(
+    /// Invariant: Contains even number of strings, which are
+    /// name, data, name, data, ...
+    Strings,
+);
+
+impl Extensions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return number of extension pairs.
+    pub fn len(&self) -> u32 {
+        self.0.len() / 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Add an extension.
+    ///
+    /// Does not check whether extension with the same name is already added,
+    /// use [`Extensions::merge`] if you want to overwrite one.
+    ///
+    /// * `name` - must not contain null byte.
+    /// * `data` - must not contain null byte.
+    pub fn add_extension(&mut self, name: &str, data: &str) {
+        self.0.push(name);
+        self.0.push(data);
+    }
+
+    /// Return data of the first extension named `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.iter()
+            .find(|(ext_name, _data)| *ext_name == name)
+            .map(|(_name, data)| data)
+    }
+
+    /// Return iterator of (name, data).
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> + '_ {
+        let mut iter = self.0.iter();
+
+        std::iter::from_fn(move || Some((iter.next()?, iter.next()?)))
+    }
+
+    /// Parse extensions from `s`, e.g. `a@example.com=1,b@example.com=2`
+    /// with `pair_sep` being `,` and `kv_sep` being `=`.
+    ///
+    /// Empty segments are skipped, so an empty `s` or a trailing
+    /// `pair_sep` is accepted.
+    ///
+    /// Return `None` if a segment does not contain `kv_sep`, has an empty
+    /// name, or contains null byte.
+    pub fn from_delimited(s: &str, pair_sep: char, kv_sep: char) -> Option<Self> {
+        let mut extensions = Self::new();
+
+        for segment in s.split(pair_sep).filter(|segment| !segment.is_empty()) {
+            let (name, data) = segment.split_once(kv_sep)?;
+
+            if name.is_empty() || segment.contains('\0') {
+                return None;
+            }
+
+            extensions.add_extension(name, data);
+        }
+
+        Some(extensions)
+    }
+
+    /// Inverse of [`Extensions::from_delimited`].
+    ///
+    /// Return `None` if a name is empty or contains `pair_sep` or `kv_sep`,
+    /// or if a data contains `pair_sep`, since it cannot be parsed back.
+    pub fn to_delimited(&self, pair_sep: char, kv_sep: char) -> Option<String> {
+        let mut s = String::new();
+
+        for (name, data) in self.iter() {
+            if name.is_empty()
+                || name.contains(pair_sep)
+                || name.contains(kv_sep)
+                || data.contains(pair_sep)
+            {
+                return None;
+            }
+
+            if !s.is_empty() {
+                s.push(pair_sep);
+            }
+            s.push_str(name);
+            s.push(kv_sep);
+            s.push_str(data);
+        }
+
+        Some(s)
+    }
+
+    /// Add extensions in `other` into `self`, overwriting data of extensions
+    /// with the same name.
+    pub fn merge(&mut self, other: &Extensions) {
+        let mut merged = Strings::with_capacity(self.0.len() + other.0.len());
+
+        for (name, data) in self.iter() {
+            merged.push(name);
+            merged.push(other.get(name).unwrap_or(data));
+        }
+
+        for (name, data) in other.iter() {
+            if self.get(name).is_none() {
+                merged.push(name);
+                merged.push(data);
+            }
+        }
+
+        self.0 = merged;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Extensions;
+
+    #[test]
+    fn test_merge() {
+        let mut extensions = Extensions::new();
+        extensions.add_extension("a", "1");
+        extensions.add_extension("b", "1");
+
+        let mut overrides = Extensions::new();
+        overrides.add_extension("b", "2");
+        overrides.add_extension("c", "2");
+
+        extensions.merge(&overrides);
+
+        assert_eq!(extensions.len(), 3);
+        assert_eq!(
+            extensions.iter().collect::<Vec<_>>(),
+            [("a", "1"), ("b", "2"), ("c", "2")]
+        );
+    }
+
+    #[test]
+    fn test_delimited() {
+        let extensions = Extensions::from_delimited("a=1,,b=x=y,c=,", ',', '=').unwrap();
+        assert_eq!(
+            extensions.iter().collect::<Vec<_>>(),
+            [("a", "1"), ("b", "x=y"), ("c", "")]
+        );
+        assert_eq!(extensions.to_delimited(',', '=').unwrap(), "a=1,b=x=y,c=");
+        assert_eq!(extensions.to_delimited(';', ':').unwrap(), "a:1;b:x=y;c:");
+
+        assert!(Extensions::from_delimited("", ',', '=').unwrap().is_empty());
+        assert!(Extensions::from_delimited("a=1,b", ',', '=').is_none());
+        assert!(Extensions::from_delimited("=1", ',', '=').is_none());
+        assert!(Extensions::from_delimited("a=\0", ',', '=').is_none());
+
+        let mut extensions = Extensions::new();
+        extensions.add_extension("a", "1,2");
+        assert!(extensions.to_delimited(',', '=').is_none());
+    }
+}
@@ -6,26 +6,29 @@ use super::{
 };
 
 use std::{
+    cmp::Ordering,
     convert::TryInto,
+    fmt,
     time::{Duration, SystemTime},
 };
 
 use bitflags::bitflags;
 use num_derive::FromPrimitive;
 use num_traits::cast::FromPrimitive;
-use openssh_sftp_protocol_error::UnixTimeStampError;
+use openssh_sftp_protocol_error::{ParsePermissionsError, UnixTimeStampError};
 use serde::{
     de::{Error, Unexpected},
-    ser::{SerializeTuple, Serializer},
+    ser::{Error as _, SerializeTuple, Serializer},
     Serialize,
 };
 
 /// bit mask for the file type bit field
-const S_IFMT: u32 = 0o170000;
+pub const S_IFMT: u32 = 0o170000;
 
 bitflags! {
+    /// Attributes present in [`FileAttrs`].
     #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
-    struct FileAttrsFlags: u8 {
+    pub struct FileAttrsFlags: u8 {
         const SIZE = 1 << 0;
         const ID = 1 << 1;
         const PERMISSIONS = 1 << 2;
@@ -33,41 +36,14 @@ bitflags! {
         const EXTENSIONS = 1 << 4;
     }
 }
-impl Serialize for FileAttrsFlags {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        use constants::{
-            SSH_FILEXFER_ATTR_ACMODTIME, SSH_FILEXFER_ATTR_PERMISSIONS, SSH_FILEXFER_ATTR_SIZE,
-            SSH_FILEXFER_ATTR_UIDGID,
-        };
-
-        let mut flags: u32 = 0;
-        if self.intersects(FileAttrsFlags::SIZE) {
-            flags |= SSH_FILEXFER_ATTR_SIZE;
-        }
-        if self.intersects(FileAttrsFlags::ID) {
-            flags |= SSH_FILEXFER_ATTR_UIDGID;
-        }
-        if self.intersects(FileAttrsFlags::PERMISSIONS) {
-            flags |= SSH_FILEXFER_ATTR_PERMISSIONS;
-        }
-        if self.intersects(FileAttrsFlags::TIME) {
-            flags |= SSH_FILEXFER_ATTR_ACMODTIME;
-        }
-
-        flags.serialize(serializer)
-    }
-}
-
-impl<'de> crate::visitor::Deserialize<'de> for FileAttrsFlags {
-    fn deserialize<D: crate::visitor::Deserializer<'de>>(
-        deserializer: D,
-    ) -> Result<Self, D::Error> {
+impl FileAttrsFlags {
+    /// Convert from the `u32` flags on the wire.
+    fn from_wire(flags: u32) -> Self {
         use constants::{
             SSH_FILEXFER_ATTR_ACMODTIME, SSH_FILEXFER_ATTR_EXTENDED, SSH_FILEXFER_ATTR_PERMISSIONS,
             SSH_FILEXFER_ATTR_SIZE, SSH_FILEXFER_ATTR_UIDGID,
         };
 
-        let flags = u32::deserialize(deserializer)?;
         let has_attr = |attr_mask| -> bool { (flags & attr_mask) != 0 };
 
         let mut file_attrs_flags = FileAttrsFlags::empty();
@@ -88,7 +64,48 @@ impl<'de> crate::visitor::Deserialize<'de> for FileAttrsFlags {
             file_attrs_flags |= FileAttrsFlags::EXTENSIONS;
         }
 
-        Ok(file_attrs_flags)
+        file_attrs_flags
+    }
+
+    /// Convert to the `u32` flags on the wire.
+    fn to_wire(self) -> u32 {
+        use constants::{
+            SSH_FILEXFER_ATTR_ACMODTIME, SSH_FILEXFER_ATTR_EXTENDED, SSH_FILEXFER_ATTR_PERMISSIONS,
+            SSH_FILEXFER_ATTR_SIZE, SSH_FILEXFER_ATTR_UIDGID,
+        };
+
+        let mut flags: u32 = 0;
+        if self.intersects(FileAttrsFlags::SIZE) {
+            flags |= SSH_FILEXFER_ATTR_SIZE;
+        }
+        if self.intersects(FileAttrsFlags::ID) {
+            flags |= SSH_FILEXFER_ATTR_UIDGID;
+        }
+        if self.intersects(FileAttrsFlags::PERMISSIONS) {
+            flags |= SSH_FILEXFER_ATTR_PERMISSIONS;
+        }
+        if self.intersects(FileAttrsFlags::TIME) {
+            flags |= SSH_FILEXFER_ATTR_ACMODTIME;
+        }
+        if self.intersects(FileAttrsFlags::EXTENSIONS) {
+            flags |= SSH_FILEXFER_ATTR_EXTENDED;
+        }
+
+        flags
+    }
+}
+
+impl Serialize for FileAttrsFlags {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_wire().serialize(serializer)
+    }
+}
+
+impl<'de> crate::visitor::Deserialize<'de> for FileAttrsFlags {
+    fn deserialize<D: crate::visitor::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        u32::deserialize(deserializer).map(FileAttrsFlags::from_wire)
     }
 }
 
@@ -138,6 +155,114 @@ bitflags! {
     }
 }
 
+impl Permissions {
+    /// Create `Permissions` from unix mode, e.g. `0o755`, with filetype
+    /// and other unknown bits removed.
+    pub const fn from_mode(mode: u32) -> Self {
+        Self::from_bits_truncate(mode)
+    }
+
+    /// Return the permission bits as unix mode.
+    pub const fn mode(&self) -> u32 {
+        self.bits()
+    }
+
+    /// Return permissions of the owner.
+    pub const fn owner(read: bool, write: bool, exec: bool) -> Self {
+        Self::triple(read, write, exec, 6)
+    }
+
+    /// Return permissions of the group.
+    pub const fn group(read: bool, write: bool, exec: bool) -> Self {
+        Self::triple(read, write, exec, 3)
+    }
+
+    /// Return permissions of others.
+    pub const fn other(read: bool, write: bool, exec: bool) -> Self {
+        Self::triple(read, write, exec, 0)
+    }
+
+    const fn triple(read: bool, write: bool, exec: bool, shift: u32) -> Self {
+        let bits = (read as u32) << 2 | (write as u32) << 1 | exec as u32;
+        Self::from_bits_truncate(bits << shift)
+    }
+
+    pub const fn is_setuid(&self) -> bool {
+        self.contains(Permissions::SET_UID)
+    }
+
+    pub const fn is_setgid(&self) -> bool {
+        self.contains(Permissions::SET_GID)
+    }
+
+    pub const fn is_sticky(&self) -> bool {
+        self.contains(Permissions::SET_VTX)
+    }
+
+    /// Parse octal mode such as `755`, `0755` or `0o755`.
+    pub fn from_octal_str(s: &str) -> Result<Self, ParsePermissionsError> {
+        let digits = s.strip_prefix("0o").unwrap_or(s);
+        if digits.is_empty() {
+            return Err(ParsePermissionsError::Empty);
+        }
+
+        let mut mode: u32 = 0;
+        for c in digits.chars() {
+            let digit = c
+                .to_digit(8)
+                .ok_or(ParsePermissionsError::InvalidDigit(c))?;
+
+            mode = mode.saturating_mul(8).saturating_add(digit);
+        }
+
+        Self::from_bits(mode).ok_or(ParsePermissionsError::OutOfRange(mode))
+    }
+}
+
+impl fmt::Display for Permissions {
+    /// Format as `rwxr-xr-x`, the same as `ls -l` without the filetype.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let triplets = [
+            (
+                Permissions::READ_BY_OWNER,
+                Permissions::WRITE_BY_OWNER,
+                Permissions::EXECUTE_BY_OWNER,
+                Permissions::SET_UID,
+                ['s', 'S'],
+            ),
+            (
+                Permissions::READ_BY_GROUP,
+                Permissions::WRITE_BY_GROUP,
+                Permissions::EXECUTE_BY_GROUP,
+                Permissions::SET_GID,
+                ['s', 'S'],
+            ),
+            (
+                Permissions::READ_BY_OTHER,
+                Permissions::WRITE_BY_OTHER,
+                Permissions::EXECUTE_BY_OTHER,
+                Permissions::SET_VTX,
+                ['t', 'T'],
+            ),
+        ];
+
+        for (read, write, execute, special, [special_exec, special_no_exec]) in triplets.iter() {
+            let flag = |perm: &Permissions, c| if self.contains(*perm) { c } else { '-' };
+
+            let execute = match (self.contains(*special), self.contains(*execute)) {
+                (true, true) => *special_exec,
+                (true, false) => *special_no_exec,
+                (false, true) => 'x',
+                (false, false) => '-',
+            };
+
+            write!(f, "{}{}{}", flag(read, 'r'), flag(write, 'w'), execute)?;
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Copy, FromPrimitive, Eq, PartialEq, Hash)]
 #[repr(u32)]
 pub enum FileType {
@@ -150,46 +275,291 @@ pub enum FileType {
     FIFO = 0o10000,
 }
 
+impl FileType {
+    /// Return filetype of unix `mode`, or `None` if filetype bits
+    /// are not set or invalid.
+    pub fn from_mode(mode: u32) -> Option<Self> {
+        FileType::from_u32(mode & S_IFMT)
+    }
+
+    /// Return filetype bits in unix mode.
+    pub const fn to_mode(self) -> u32 {
+        self as u32
+    }
+
+    /// Return the leading character of the longname (in the format of `ls -l`)
+    /// for this filetype.
+    pub const fn to_longname_char(self) -> char {
+        use FileType::*;
+
+        match self {
+            Socket => 's',
+            Symlink => 'l',
+            RegularFile => '-',
+            BlockDevice => 'b',
+            Directory => 'd',
+            CharacterDevice => 'c',
+            FIFO => 'p',
+        }
+    }
+
+    /// Inverse of [`FileType::to_longname_char`].
+    pub const fn from_longname_char(c: char) -> Option<Self> {
+        use FileType::*;
+
+        match c {
+            's' => Some(Socket),
+            'l' => Some(Symlink),
+            '-' => Some(RegularFile),
+            'b' => Some(BlockDevice),
+            'd' => Some(Directory),
+            'c' => Some(CharacterDevice),
+            'p' => Some(FIFO),
+            _ => None,
+        }
+    }
+
+    /// Convert from [`std::fs::FileType`], return `None` if it cannot be
+    /// represented in the protocol.
+    #[cfg(unix)]
+    pub fn from_std(filetype: std::fs::FileType) -> Option<Self> {
+        use std::os::unix::fs::FileTypeExt;
+
+        use FileType::*;
+
+        if filetype.is_dir() {
+            Some(Directory)
+        } else if filetype.is_file() {
+            Some(RegularFile)
+        } else if filetype.is_symlink() {
+            Some(Symlink)
+        } else if filetype.is_socket() {
+            Some(Socket)
+        } else if filetype.is_fifo() {
+            Some(FIFO)
+        } else if filetype.is_block_device() {
+            Some(BlockDevice)
+        } else if filetype.is_char_device() {
+            Some(CharacterDevice)
+        } else {
+            None
+        }
+    }
+
+    pub const fn is_dir(self) -> bool {
+        matches!(self, FileType::Directory)
+    }
+
+    pub const fn is_file(self) -> bool {
+        matches!(self, FileType::RegularFile)
+    }
+
+    pub const fn is_symlink(self) -> bool {
+        matches!(self, FileType::Symlink)
+    }
+
+    pub const fn is_fifo(self) -> bool {
+        matches!(self, FileType::FIFO)
+    }
+
+    pub const fn is_socket(self) -> bool {
+        matches!(self, FileType::Socket)
+    }
+
+    pub const fn is_block_device(self) -> bool {
+        matches!(self, FileType::BlockDevice)
+    }
+
+    pub const fn is_char_device(self) -> bool {
+        matches!(self, FileType::CharacterDevice)
+    }
+}
+
+impl fmt::Display for FileType {
+    /// Same as [`FileType::to_longname_char`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_longname_char())
+    }
+}
+
 /// Default value is 1970-01-01 00:00:00 UTC.
 ///
-/// UnixTimeStamp stores number of seconds elapsed since 1970-01-01 00:00:00 UTC
-/// as `u32`.
+/// UnixTimeStamp stores number of seconds elapsed since 1970-01-01 00:00:00 UTC,
+/// which is negative for timestamps earlier than that.
+///
+/// On the wire, it is sent as 32-bit, which is interpreted as signed when
+/// decoded, same as openssh, so timestamps after 2038 are sent as is but
+/// decoded as earlier than unix epoch.
+///
+/// [`UnixTimeStamp::from_raw`] and [`UnixTimeStamp::from_raw_signed`] can
+/// be used to interpret the 32-bit value either way.
+///
+/// It can also store nanoseconds, which are sent by sftp v4+ servers, but
+/// it is not included in the 32-bit value on the wire.
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
-#[repr(transparent)]
-pub struct UnixTimeStamp(u32);
+pub struct UnixTimeStamp {
+    seconds: i64,
+
+    /// Always less than 1_000_000_000.
+    nanos: u32,
+}
 
 impl UnixTimeStamp {
+    /// Return error if `system_time` is not within
+    /// `[i32::MIN, u32::MAX]` seconds since unix epoch.
+    ///
+    /// Nanoseconds of `system_time` are preserved.
     pub fn new(system_time: SystemTime) -> Result<Self, UnixTimeStampError> {
-        let duration = system_time.duration_since(SystemTime::UNIX_EPOCH)?;
-        let seconds: u32 = duration.as_secs().try_into()?;
-        Ok(Self(seconds))
+        match system_time.duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(duration) => {
+                let seconds: u32 = duration.as_secs().try_into()?;
+                Ok(Self::from_parts(seconds as i64, duration.subsec_nanos()))
+            }
+            Err(err) => {
+                let duration = err.duration();
+                let subsec_nanos = duration.subsec_nanos();
+
+                // Round seconds towards negative infinity, so that nanos
+                // are counted forward from it.
+                let seconds = duration.as_secs() + (subsec_nanos != 0) as u64;
+                let nanos = if subsec_nanos != 0 {
+                    1_000_000_000 - subsec_nanos
+                } else {
+                    0
+                };
+
+                if seconds > i32::MIN.unsigned_abs() as u64 {
+                    Err(err.into())
+                } else {
+                    Ok(Self::from_parts(-(seconds as i64), nanos))
+                }
+            }
+        }
+    }
+
+    /// Return the current time.
+    ///
+    /// It fails after year 2106, when the number of seconds since unix
+    /// epoch no longer fits in `u32`.
+    pub fn now() -> Result<Self, UnixTimeStampError> {
+        Self::new(SystemTime::now())
+    }
+
+    /// Convert `system_time` for display purposes, never failing.
+    ///
+    /// Return [`UnixTimeStamp::unix_epoch`] if `system_time` is earlier than
+    /// unix epoch, or `u32::MAX` seconds after unix epoch if it is later
+    /// than that, otherwise same as [`UnixTimeStamp::new`].
+    pub fn saturating_from_system_time(system_time: SystemTime) -> Self {
+        if system_time < SystemTime::UNIX_EPOCH {
+            return Self::unix_epoch();
+        }
+
+        Self::new(system_time).unwrap_or_else(|_| Self::from_parts(u32::MAX as i64, 0))
     }
 
     /// Return unix epoch, same as [`UnixTimeStamp::default`]
     pub const fn unix_epoch() -> Self {
-        Self(0)
+        Self::from_parts(0, 0)
+    }
+
+    const fn from_parts(seconds: i64, nanos: u32) -> Self {
+        Self { seconds, nanos }
     }
 
     /// Return `None` if [`std::time::SystemTime`] cannot hold the timestamp.
     pub fn from_raw(elapsed: u32) -> Option<Self> {
-        let this = Self(elapsed);
+        Self::with_nanos(elapsed as i64, 0)
+    }
+
+    /// Same as [`UnixTimeStamp::from_raw`], but `elapsed` can be negative
+    /// for timestamps earlier than unix epoch.
+    pub fn from_raw_signed(elapsed: i32) -> Option<Self> {
+        Self::with_nanos(elapsed as i64, 0)
+    }
 
-        let duration = this.as_duration();
-        SystemTime::UNIX_EPOCH.checked_add(duration)?;
+    /// Create a timestamp `seconds` (negative if earlier than unix epoch)
+    /// plus `nanos` nanoseconds after unix epoch.
+    ///
+    /// Return `None` if `nanos` is not less than 1_000_000_000 or
+    /// [`std::time::SystemTime`] cannot hold the timestamp.
+    pub fn with_nanos(seconds: i64, nanos: u32) -> Option<Self> {
+        if nanos >= 1_000_000_000 {
+            return None;
+        }
+
+        let this = Self::from_parts(seconds, nanos);
+
+        // Make sure it can be converted into SystemTime
+        this.checked_system_time()?;
 
         Some(this)
     }
 
+    /// Return the 32-bit value on the wire, negative timestamp is
+    /// represented in two's complement.
+    ///
+    /// Nanoseconds are not included.
     pub fn into_raw(self) -> u32 {
-        self.0
+        self.seconds as u32
+    }
+
+    /// Return the 32-bit value on the wire as signed.
+    pub fn into_raw_signed(self) -> i32 {
+        self.seconds as i32
+    }
+
+    /// Return the nanoseconds part of the timestamp.
+    pub fn subsec_nanos(self) -> u32 {
+        self.nanos
+    }
+
+    /// Return true if the timestamp is earlier than unix epoch.
+    pub fn is_before_epoch(self) -> bool {
+        self.seconds < 0
+    }
+
+    /// Return time elapsed since unix epoch, or `None` if the timestamp
+    /// is earlier than unix epoch.
+    ///
+    /// Use [`UnixTimeStamp::as_system_time`] to get timestamps earlier than
+    /// unix epoch.
+    pub fn as_duration(self) -> Option<Duration> {
+        if self.is_before_epoch() {
+            None
+        } else {
+            Some(Duration::new(self.seconds as u64, self.nanos))
+        }
     }
 
-    pub fn as_duration(self) -> Duration {
-        Duration::from_secs(self.0 as u64)
+    fn checked_system_time(self) -> Option<SystemTime> {
+        let seconds = Duration::from_secs(self.seconds.unsigned_abs());
+
+        let system_time = if self.is_before_epoch() {
+            SystemTime::UNIX_EPOCH.checked_sub(seconds)
+        } else {
+            SystemTime::UNIX_EPOCH.checked_add(seconds)
+        }?;
+
+        system_time.checked_add(Duration::from_nanos(self.nanos as u64))
     }
 
     pub fn as_system_time(self) -> SystemTime {
-        SystemTime::UNIX_EPOCH + self.as_duration()
+        self.checked_system_time()
+            .expect("UnixTimeStamp is checked on creation")
+    }
+}
+
+impl PartialOrd for UnixTimeStamp {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for UnixTimeStamp {
+    /// Compare seconds, then nanoseconds.
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.seconds, self.nanos).cmp(&(other.seconds, other.nanos))
     }
 }
 
@@ -200,11 +570,11 @@ impl_visitor!(
     seq,
     {
         let mut iter = SeqIter::new(seq);
-        let elapsed: u32 = iter.get_next()?;
+        let elapsed: i32 = iter.get_next()?;
 
-        let timestamp = UnixTimeStamp::from_raw(elapsed).ok_or_else(|| {
+        let timestamp = UnixTimeStamp::from_raw_signed(elapsed).ok_or_else(|| {
             V::Error::invalid_value(
-                Unexpected::Unsigned(elapsed as u64),
+                Unexpected::Signed(elapsed as i64),
                 &"Invalid UnixTimeStamp (seconds)",
             )
         })?;
@@ -213,7 +583,19 @@ impl_visitor!(
     }
 );
 
-#[derive(Debug, Default, Copy, Clone)]
+/// Maximum length of the name of an extended attribute accepted on decoding.
+const MAX_EXTENSION_NAME_LEN: usize = 1024;
+/// Maximum length of the data of an extended attribute accepted on decoding.
+const MAX_EXTENSION_DATA_LEN: usize = 64 * 1024;
+
+/// (name, data) of an extended attribute.
+pub type ExtensionPair = (Box<[u8]>, Box<[u8]>);
+
+/// Attributes of a file.
+///
+/// `FileAttrs` is not `Copy` since it owns the extended attributes sent by
+/// the server.
+#[derive(Debug, Default, Clone)]
 pub struct FileAttrs {
     flags: FileAttrsFlags,
 
@@ -230,21 +612,29 @@ pub struct FileAttrs {
     /// present only if flag SSH_FILEXFER_ATTR_ACMODTIME
     atime: UnixTimeStamp,
     mtime: UnixTimeStamp,
+
+    /// extended attributes (name, data), present only if flag
+    /// SSH_FILEXFER_ATTR_EXTENDED.
+    ///
+    /// Unlike other attributes, `flags` never contains
+    /// `FileAttrsFlags::EXTENSIONS`, it is set on serialization if and only
+    /// if `extensions` is not empty.
+    extensions: Vec<ExtensionPair>,
 }
 
 impl PartialEq for FileAttrs {
     fn eq(&self, other: &Self) -> bool {
-        self.get_size() == other.get_size()
-            && self.get_id() == other.get_id()
-            && self.get_permissions() == other.get_permissions()
-            && self.get_filetype() == other.get_filetype()
-            && self.get_time() == other.get_time()
+        self.eq_ignoring(other, FileAttrsFlags::empty())
     }
 }
 
 impl Eq for FileAttrs {}
 
 impl FileAttrs {
+    pub const fn builder() -> FileAttrsBuilder {
+        FileAttrsBuilder::new()
+    }
+
     pub const fn new() -> Self {
         Self {
             flags: FileAttrsFlags::empty(),
@@ -257,6 +647,26 @@ impl FileAttrs {
 
             atime: UnixTimeStamp::unix_epoch(),
             mtime: UnixTimeStamp::unix_epoch(),
+
+            extensions: Vec::new(),
+        }
+    }
+
+    /// Create `FileAttrs` with only filetype and permissions set.
+    pub const fn from_type_and_permissions(filetype: FileType, permissions: Permissions) -> Self {
+        Self {
+            flags: FileAttrsFlags::PERMISSIONS,
+            size: 0,
+
+            uid: 0,
+            gid: 0,
+
+            st_mode: filetype as u32 | permissions.bits(),
+
+            atime: UnixTimeStamp::unix_epoch(),
+            mtime: UnixTimeStamp::unix_epoch(),
+
+            extensions: Vec::new(),
         }
     }
 
@@ -277,12 +687,69 @@ impl FileAttrs {
         self.st_mode = filetype | permissions.bits();
     }
 
+    /// Set filetype while preserving the permissions.
+    ///
+    /// Unlike other attributes, filetype is usually only sent by the
+    /// sftp-server, e.g. in [`crate::response::ResponseInner::Attrs`].
+    pub fn set_filetype(&mut self, filetype: FileType) {
+        self.flags |= FileAttrsFlags::PERMISSIONS;
+        let permissions = self.st_mode & !S_IFMT;
+        self.st_mode = filetype as u32 | permissions;
+    }
+
     pub fn set_time(&mut self, atime: UnixTimeStamp, mtime: UnixTimeStamp) {
         self.flags |= FileAttrsFlags::TIME;
         self.atime = atime;
         self.mtime = mtime;
     }
 
+    /// Overlay `other` onto `self`: every attribute set in `other`
+    /// replaces the one in `self`, while the others are left intact.
+    ///
+    /// If `other` has permissions set, then filetype is replaced as well,
+    /// unless `other` does not have a filetype, in which case the filetype
+    /// of `self` is kept.
+    pub fn merge(&mut self, other: &FileAttrs) {
+        if let Some(size) = other.get_size() {
+            self.set_size(size);
+        }
+        if let Some((uid, gid)) = other.get_id() {
+            self.set_id(uid, gid);
+        }
+        if let Some(permissions) = other.get_permissions() {
+            self.set_permissions(permissions);
+        }
+        if let Some(filetype) = other.get_filetype() {
+            self.set_filetype(filetype);
+        }
+        if let Some((atime, mtime)) = other.get_time() {
+            self.set_time(atime, mtime);
+        }
+    }
+
+    pub fn clear_size(&mut self) {
+        self.flags.remove(FileAttrsFlags::SIZE);
+        self.size = 0;
+    }
+
+    pub fn clear_id(&mut self) {
+        self.flags.remove(FileAttrsFlags::ID);
+        self.uid = 0;
+        self.gid = 0;
+    }
+
+    /// Clear both permissions and filetype.
+    pub fn clear_permissions(&mut self) {
+        self.flags.remove(FileAttrsFlags::PERMISSIONS);
+        self.st_mode = 0;
+    }
+
+    pub fn clear_time(&mut self) {
+        self.flags.remove(FileAttrsFlags::TIME);
+        self.atime = UnixTimeStamp::unix_epoch();
+        self.mtime = UnixTimeStamp::unix_epoch();
+    }
+
     fn has_attr(&self, flag: FileAttrsFlags) -> bool {
         self.flags.intersects(flag)
     }
@@ -313,13 +780,8 @@ impl FileAttrs {
     /// filetype is only set by the sftp-server.
     pub fn get_filetype(&self) -> Option<FileType> {
         self.getter_impl(FileAttrsFlags::PERMISSIONS, || {
-            let filetype = self.st_mode & S_IFMT;
-
-            if filetype == 0 {
-                None
-            } else {
-                Some(FileType::from_u32(filetype).unwrap())
-            }
+            // The deserializer has validated the filetype.
+            FileType::from_mode(self.st_mode)
         })
         .flatten()
     }
@@ -328,37 +790,245 @@ impl FileAttrs {
     pub fn get_time(&self) -> Option<(UnixTimeStamp, UnixTimeStamp)> {
         self.getter_impl(FileAttrsFlags::TIME, || (self.atime, self.mtime))
     }
+
+    /// Return the `u32` flags that would be serialized, which is a
+    /// combination of `constants::SSH_FILEXFER_ATTR_*` and includes
+    /// `constants::SSH_FILEXFER_ATTR_EXTENDED` if there is any
+    /// extended attribute.
+    ///
+    /// For deserialized `FileAttrs`, this is the flags sent by the server
+    /// with unknown bits removed, except that `SSH_FILEXFER_ATTR_EXTENDED`
+    /// is not set if the server sent zero extended attributes.
+    #[doc(alias = "flags_raw")]
+    pub fn flags_wire(&self) -> u32 {
+        self.flags_with_extensions().to_wire()
+    }
+
+    /// Return the attributes present, including
+    /// [`FileAttrsFlags::EXTENSIONS`] if there is any extended attribute.
+    pub fn flags(&self) -> FileAttrsFlags {
+        self.flags_with_extensions()
+    }
+
+    /// Same as `==`, except that attributes in `ignore` are not compared,
+    /// e.g. ignore [`FileAttrsFlags::TIME`] since atime changes on every
+    /// read.
+    ///
+    /// Filetype is compared as part of [`FileAttrsFlags::PERMISSIONS`].
+    pub fn eq_ignoring(&self, other: &FileAttrs, ignore: FileAttrsFlags) -> bool {
+        let ignored = |flag| ignore.contains(flag);
+
+        (ignored(FileAttrsFlags::SIZE) || self.get_size() == other.get_size())
+            && (ignored(FileAttrsFlags::ID) || self.get_id() == other.get_id())
+            && (ignored(FileAttrsFlags::PERMISSIONS)
+                || (self.get_permissions() == other.get_permissions()
+                    && self.get_filetype() == other.get_filetype()))
+            && (ignored(FileAttrsFlags::TIME) || self.get_time() == other.get_time())
+            && (ignored(FileAttrsFlags::EXTENSIONS) || self.extensions == other.extensions)
+    }
+
+    /// Check that all attributes in `required` are present, e.g. after
+    /// a `Stat` to verify the server did not omit any of them.
+    ///
+    /// Return the missing attributes on failure.
+    pub fn assert_has(&self, required: FileAttrsFlags) -> Result<(), FileAttrsFlags> {
+        let missing = required - self.flags();
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(missing)
+        }
+    }
+
+    fn flags_with_extensions(&self) -> FileAttrsFlags {
+        if self.extensions.is_empty() {
+            self.flags
+        } else {
+            self.flags | FileAttrsFlags::EXTENSIONS
+        }
+    }
+
+    /// Return extended attributes (name, data).
+    pub fn get_extensions(&self) -> &[ExtensionPair] {
+        &self.extensions
+    }
+
+    /// Replace extended attributes (name, data), they are only sent if
+    /// `extensions` is not empty.
+    pub fn set_extensions(&mut self, extensions: Vec<ExtensionPair>) {
+        self.extensions = extensions;
+    }
+
+    /// Return data of the first extended attribute named `name`.
+    fn get_extension(&self, name: &[u8]) -> Option<&[u8]> {
+        self.extensions
+            .iter()
+            .find(|(ext_name, _data)| &**ext_name == name)
+            .map(|(_name, data)| &**data)
+    }
+
+    /// Return mime type of the file, which is sent by some sftp v6 servers
+    /// as extended attribute `mime-type`.
+    pub fn mime_type(&self) -> Option<&str> {
+        std::str::from_utf8(self.get_extension(b"mime-type")?).ok()
+    }
 }
 
-impl Serialize for FileAttrs {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+/// Builder for [`FileAttrs`], with each method setting the attribute
+/// the same way as the setters of [`FileAttrs`].
+#[derive(Debug, Default, Clone)]
+pub struct FileAttrsBuilder(FileAttrs);
+
+impl FileAttrsBuilder {
+    pub const fn new() -> Self {
+        Self(FileAttrs::new())
+    }
+
+    pub fn size(mut self, size: u64) -> Self {
+        self.0.set_size(size);
+        self
+    }
+
+    pub fn id(mut self, uid: u32, gid: u32) -> Self {
+        self.0.set_id(uid, gid);
+        self
+    }
+
+    pub fn permissions(mut self, permissions: Permissions) -> Self {
+        self.0.set_permissions(permissions);
+        self
+    }
+
+    pub fn time(mut self, atime: UnixTimeStamp, mtime: UnixTimeStamp) -> Self {
+        self.0.set_time(atime, mtime);
+        self
+    }
+
+    pub fn build(self) -> FileAttrs {
+        self.0
+    }
+}
+
+/// Records which attributes a server returns, by feeding it the
+/// [`FileAttrs`] in its responses.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct AttrCapabilityProbe {
+    flags: u32,
+    has_filetype: bool,
+    samples: u64,
+}
+
+impl AttrCapabilityProbe {
+    pub const fn new() -> Self {
+        Self {
+            flags: 0,
+            has_filetype: false,
+            samples: 0,
+        }
+    }
+
+    pub fn observe(&mut self, attrs: &FileAttrs) {
+        self.flags |= attrs.flags_wire();
+        self.has_filetype |= attrs.get_filetype().is_some();
+        self.samples += 1;
+    }
+
+    /// Return union of [`FileAttrs::flags_wire`] of all attributes observed.
+    pub const fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// Return true if any of the attributes observed has all flags in
+    /// `mask` set, where `mask` is a combination of
+    /// `constants::SSH_FILEXFER_ATTR_*`.
+    pub const fn has(&self, mask: u32) -> bool {
+        (self.flags & mask) == mask
+    }
+
+    /// Return true if the server has returned the filetype in permissions.
+    pub const fn has_filetype(&self) -> bool {
+        self.has_filetype
+    }
+
+    /// Return number of attributes observed.
+    pub const fn samples(&self) -> u64 {
+        self.samples
+    }
+}
+
+impl FileAttrs {
+    /// Serialize only the attributes that are both set in `self` and
+    /// in `mask`, which is a combination of `constants::SSH_FILEXFER_ATTR_*`.
+    ///
+    /// The flags serialized would also be the intersection of the flags of
+    /// `self` and `mask`.
+    pub fn serialize_subset<S: Serializer>(
+        &self,
+        serializer: S,
+        mask: u32,
+    ) -> Result<S::Ok, S::Error> {
+        self.serialize_impl(
+            serializer,
+            self.flags_with_extensions() & FileAttrsFlags::from_wire(mask),
+        )
+    }
+
+    fn serialize_impl<S: Serializer>(
+        &self,
+        serializer: S,
+        flags: FileAttrsFlags,
+    ) -> Result<S::Ok, S::Error> {
+        let has_attr = |flag| flags.intersects(flag);
+
         // dummy size since ssh_format doesn't care
         let mut tuple_serializer = serializer.serialize_tuple(1)?;
 
-        tuple_serializer.serialize_element(&self.flags)?;
+        tuple_serializer.serialize_element(&flags)?;
+
+        if has_attr(FileAttrsFlags::SIZE) {
+            tuple_serializer.serialize_element(&self.size)?;
+        }
 
-        if let Some(size) = self.get_size() {
-            tuple_serializer.serialize_element(&size)?;
+        if has_attr(FileAttrsFlags::ID) {
+            tuple_serializer.serialize_element(&self.uid)?;
+            tuple_serializer.serialize_element(&self.gid)?;
         }
 
-        if let Some((uid, gid)) = self.get_id() {
-            tuple_serializer.serialize_element(&uid)?;
-            tuple_serializer.serialize_element(&gid)?;
+        if has_attr(FileAttrsFlags::PERMISSIONS) {
+            // filetype is only present if set by the server or by
+            // `FileAttrs::set_filetype`.
+            tuple_serializer.serialize_element(&self.st_mode)?;
         }
 
-        if let Some(perm) = self.get_permissions() {
-            tuple_serializer.serialize_element(&perm.bits())?;
+        if has_attr(FileAttrsFlags::TIME) {
+            tuple_serializer.serialize_element(&self.atime.into_raw())?;
+            tuple_serializer.serialize_element(&self.mtime.into_raw())?;
         }
 
-        if let Some((atime, mtime)) = self.get_time() {
-            tuple_serializer.serialize_element(&atime.into_raw())?;
-            tuple_serializer.serialize_element(&mtime.into_raw())?;
+        if has_attr(FileAttrsFlags::EXTENSIONS) {
+            let len: u32 =
+                self.extensions.len().try_into().map_err(|_| {
+                    S::Error::custom("Too many extended attributes to be serialized")
+                })?;
+            tuple_serializer.serialize_element(&len)?;
+
+            for (name, data) in &self.extensions {
+                tuple_serializer.serialize_element(&**name)?;
+                tuple_serializer.serialize_element(&**data)?;
+            }
         }
 
         tuple_serializer.end()
     }
 }
 
+impl Serialize for FileAttrs {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.serialize_impl(serializer, self.flags_with_extensions())
+    }
+}
+
 impl_visitor!(FileAttrs, FileAttrVisitor, "File attributes", seq, {
     let mut iter = SeqIter::new(seq);
     let mut attrs = FileAttrs {
@@ -387,10 +1057,11 @@ impl_visitor!(FileAttrs, FileAttrVisitor, "File attributes", seq, {
         }
     }
 
-    let into_timestamp = |elapsed: u32| {
-        let timestamp = UnixTimeStamp::from_raw(elapsed).ok_or_else(|| {
+    // Timestamps are signed on the wire, see UnixTimeStamp
+    let into_timestamp = |elapsed: i32| {
+        let timestamp = UnixTimeStamp::from_raw_signed(elapsed).ok_or_else(|| {
             V::Error::invalid_value(
-                Unexpected::Unsigned(elapsed as u64),
+                Unexpected::Signed(elapsed as i64),
                 &"Invalid UnixTimeStamp (seconds)",
             )
         })?;
@@ -406,8 +1077,10 @@ impl_visitor!(FileAttrs, FileAttrVisitor, "File attributes", seq, {
     if attrs.has_attr(FileAttrsFlags::EXTENSIONS) {
         let extension_pairs: u32 = iter.get_next()?;
         for _i in 0..extension_pairs {
-            let _name: &[u8] = iter.get_next()?;
-            let _value: &[u8] = iter.get_next()?;
+            let name = iter.get_next_bytes_capped(MAX_EXTENSION_NAME_LEN)?;
+            let data = iter.get_next_bytes_capped(MAX_EXTENSION_DATA_LEN)?;
+
+            attrs.extensions.push((name.into(), data.into()));
         }
     }
 
@@ -418,11 +1091,16 @@ impl_visitor!(FileAttrs, FileAttrVisitor, "File attributes", seq, {
 
 #[cfg(test)]
 mod tests {
-    use super::{FileAttrs, FileAttrsFlags, FileType, Permissions, UnixTimeStamp};
+    use std::time::{Duration, SystemTime};
+
+    use super::{
+        AttrCapabilityProbe, FileAttrs, FileAttrsFlags, FileType, Permissions, UnixTimeStamp,
+        MAX_EXTENSION_DATA_LEN, MAX_EXTENSION_NAME_LEN, S_IFMT,
+    };
 
     use super::constants::{
-        SSH_FILEXFER_ATTR_ACMODTIME, SSH_FILEXFER_ATTR_PERMISSIONS, SSH_FILEXFER_ATTR_SIZE,
-        SSH_FILEXFER_ATTR_UIDGID,
+        SSH_FILEXFER_ATTR_ACMODTIME, SSH_FILEXFER_ATTR_EXTENDED, SSH_FILEXFER_ATTR_PERMISSIONS,
+        SSH_FILEXFER_ATTR_SIZE, SSH_FILEXFER_ATTR_UIDGID,
     };
 
     // Test getter and setters
@@ -434,6 +1112,46 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_eq_ignoring() {
+        let (atime, mtime) = get_unix_timestamps();
+
+        let mut attrs = FileAttrs::new();
+        attrs.set_size(100);
+        attrs.set_time(atime, mtime);
+
+        let mut other = attrs.clone();
+        other.set_time(mtime, mtime);
+
+        assert_ne!(attrs, other);
+        assert!(attrs.eq_ignoring(&other, FileAttrsFlags::TIME));
+        assert!(!attrs.eq_ignoring(&other, FileAttrsFlags::SIZE));
+
+        other.set_size(200);
+        assert!(!attrs.eq_ignoring(&other, FileAttrsFlags::TIME));
+        assert!(attrs.eq_ignoring(&other, FileAttrsFlags::TIME | FileAttrsFlags::SIZE));
+        assert!(attrs.eq_ignoring(&other, FileAttrsFlags::all()));
+
+        assert_eq!(attrs.flags(), FileAttrsFlags::SIZE | FileAttrsFlags::TIME);
+    }
+
+    #[test]
+    fn test_assert_has() {
+        let mut attrs = FileAttrs::new();
+        attrs.set_size(100);
+        attrs.set_permissions(Permissions::READ_BY_OWNER);
+
+        assert_eq!(attrs.assert_has(FileAttrsFlags::empty()), Ok(()));
+        assert_eq!(
+            attrs.assert_has(FileAttrsFlags::SIZE | FileAttrsFlags::PERMISSIONS),
+            Ok(())
+        );
+        assert_eq!(
+            attrs.assert_has(FileAttrsFlags::SIZE | FileAttrsFlags::ID | FileAttrsFlags::TIME),
+            Err(FileAttrsFlags::ID | FileAttrsFlags::TIME)
+        );
+    }
+
     #[test]
     fn test_set_get_size() {
         let mut attrs = FileAttrs::default();
@@ -455,6 +1173,237 @@ mod tests {
         assert_eq!(attrs.get_permissions().unwrap(), Permissions::SET_GID);
     }
 
+    #[test]
+    fn test_unix_timestamp_signed() {
+        let before_epoch = SystemTime::UNIX_EPOCH - Duration::from_secs(100);
+
+        let timestamp = UnixTimeStamp::new(before_epoch).unwrap();
+        assert!(timestamp.is_before_epoch());
+        assert_eq!(timestamp.into_raw_signed(), -100);
+        assert_eq!(timestamp.as_system_time(), before_epoch);
+        assert_eq!(timestamp.as_duration(), None);
+        assert_eq!(UnixTimeStamp::from_raw_signed(-100).unwrap(), timestamp);
+
+        // Same bits on the wire
+        assert_eq!(timestamp.into_raw(), (-100_i32) as u32);
+
+        let timestamp = UnixTimeStamp::new(before_epoch - Duration::from_millis(500)).unwrap();
+        assert_eq!(timestamp.into_raw_signed(), -101);
+
+        UnixTimeStamp::new(SystemTime::UNIX_EPOCH - Duration::from_secs(1 << 32)).unwrap_err();
+
+        // Unsigned values are unchanged
+        let timestamp = UnixTimeStamp::from_raw(u32::MAX).unwrap();
+        assert!(!timestamp.is_before_epoch());
+        assert_eq!(timestamp.into_raw(), u32::MAX);
+        assert_eq!(
+            timestamp.as_duration(),
+            Some(Duration::from_secs(u32::MAX as u64))
+        );
+    }
+
+    #[test]
+    fn test_unix_timestamp_signed_round_trip() {
+        let atime = UnixTimeStamp::from_raw_signed(-1).unwrap();
+        let mtime = UnixTimeStamp::from_raw_signed(i32::MIN).unwrap();
+
+        let mut attrs = FileAttrs::new();
+        attrs.set_time(atime, mtime);
+
+        let bytes = ssh_format::to_bytes(&attrs).unwrap();
+        assert_eq!(&bytes[8..12], &[0xff; 4]);
+
+        let (decoded, _) = ssh_format::from_bytes::<FileAttrs>(&bytes[4..]).unwrap();
+        assert_eq!(decoded.get_time(), Some((atime, mtime)));
+        assert!(decoded.get_time().unwrap().0.is_before_epoch());
+
+        let (timestamp, _) = ssh_format::from_bytes::<UnixTimeStamp>(&bytes[8..12]).unwrap();
+        assert_eq!(timestamp, atime);
+    }
+
+    #[test]
+    fn test_unix_timestamp_nanos() {
+        let timestamp = UnixTimeStamp::with_nanos(100, 500).unwrap();
+        assert_eq!(timestamp.subsec_nanos(), 500);
+        assert_eq!(timestamp.as_duration(), Some(Duration::new(100, 500)));
+        assert_eq!(
+            timestamp.as_system_time(),
+            SystemTime::UNIX_EPOCH + Duration::new(100, 500)
+        );
+        assert_eq!(timestamp.into_raw(), 100);
+
+        let timestamp = UnixTimeStamp::with_nanos(-2, 500_000_000).unwrap();
+        assert_eq!(
+            timestamp.as_system_time(),
+            SystemTime::UNIX_EPOCH - Duration::from_millis(1500)
+        );
+
+        assert!(UnixTimeStamp::with_nanos(0, 1_000_000_000).is_none());
+        assert_eq!(UnixTimeStamp::from_raw(100).unwrap().subsec_nanos(), 0);
+
+        // Nanoseconds of SystemTime are kept
+        let system_time = SystemTime::UNIX_EPOCH + Duration::new(100, 500);
+        let timestamp = UnixTimeStamp::new(system_time).unwrap();
+        assert_eq!(timestamp, UnixTimeStamp::with_nanos(100, 500).unwrap());
+        assert_eq!(timestamp.as_system_time(), system_time);
+
+        let system_time = SystemTime::UNIX_EPOCH - Duration::from_millis(1500);
+        let timestamp = UnixTimeStamp::new(system_time).unwrap();
+        assert_eq!(
+            timestamp,
+            UnixTimeStamp::with_nanos(-2, 500_000_000).unwrap()
+        );
+        assert_eq!(timestamp.into_raw_signed(), -2);
+        assert_eq!(timestamp.as_system_time(), system_time);
+
+        let system_time = SystemTime::UNIX_EPOCH - Duration::from_secs(2);
+        let timestamp = UnixTimeStamp::new(system_time).unwrap();
+        assert_eq!(timestamp, UnixTimeStamp::from_raw_signed(-2).unwrap());
+        assert_eq!(timestamp.as_system_time(), system_time);
+
+        // Only seconds are sent on the wire
+        let mut attrs = FileAttrs::new();
+        attrs.set_time(
+            UnixTimeStamp::with_nanos(1, 2).unwrap(),
+            UnixTimeStamp::with_nanos(3, 4).unwrap(),
+        );
+        assert_eq!(
+            &ssh_format::to_bytes(&attrs).unwrap()[8..],
+            &[0, 0, 0, 1, 0, 0, 0, 3]
+        );
+    }
+
+    #[test]
+    fn test_unix_timestamp_now() {
+        let before = SystemTime::now() - Duration::from_secs(1);
+        let now = UnixTimeStamp::now().unwrap();
+
+        assert!(now.as_system_time() >= before);
+        assert!(now.as_system_time() <= SystemTime::now());
+    }
+
+    #[test]
+    fn test_unix_timestamp_saturating() {
+        let saturating = UnixTimeStamp::saturating_from_system_time;
+        let epoch = SystemTime::UNIX_EPOCH;
+
+        assert_eq!(
+            saturating(epoch + Duration::from_secs(150)),
+            UnixTimeStamp::from_raw(150).unwrap()
+        );
+        assert_eq!(
+            saturating(epoch + Duration::from_secs(1 << 33)),
+            UnixTimeStamp::from_raw(u32::MAX).unwrap()
+        );
+        assert_eq!(
+            saturating(epoch - Duration::from_secs(1 << 33)),
+            UnixTimeStamp::unix_epoch()
+        );
+        assert_eq!(
+            saturating(epoch - Duration::from_secs(1)),
+            UnixTimeStamp::unix_epoch()
+        );
+    }
+
+    #[test]
+    fn test_unix_timestamp_ord() {
+        assert!(UnixTimeStamp::from_raw(2) < UnixTimeStamp::from_raw(150));
+
+        let (atime, mtime) = get_unix_timestamps();
+        assert!(atime < mtime);
+
+        assert!(UnixTimeStamp::with_nanos(2, 1).unwrap() > atime);
+        assert!(UnixTimeStamp::with_nanos(2, 1) < UnixTimeStamp::with_nanos(2, 2));
+        assert!(UnixTimeStamp::from_raw_signed(-1).unwrap() < UnixTimeStamp::unix_epoch());
+    }
+
+    #[test]
+    fn test_permissions_mode() {
+        let permissions = Permissions::from_mode(0o755);
+        assert_eq!(permissions.mode(), 0o755);
+        assert!(permissions.contains(Permissions::READ_BY_OWNER | Permissions::EXECUTE_BY_OTHER));
+        assert!(!permissions.contains(Permissions::WRITE_BY_GROUP));
+
+        let mode = FileType::Directory as u32 | 0o4755;
+        assert_eq!(Permissions::from_mode(mode).mode(), 0o4755);
+    }
+
+    #[test]
+    fn test_permissions_display() {
+        let display = |mode| Permissions::from_mode(mode).to_string();
+
+        assert_eq!(display(0o755), "rwxr-xr-x");
+        assert_eq!(display(0o644), "rw-r--r--");
+        assert_eq!(display(0), "---------");
+        assert_eq!(display(0o4755), "rwsr-xr-x");
+        assert_eq!(display(0o4644), "rwSr--r--");
+        assert_eq!(display(0o2750), "rwxr-s---");
+        assert_eq!(display(0o2740), "rwxr-S---");
+        assert_eq!(display(0o1777), "rwxrwxrwt");
+        assert_eq!(display(0o1776), "rwxrwxrwT");
+    }
+
+    #[test]
+    fn test_permissions_triples() {
+        let perm = Permissions::owner(true, true, true)
+            | Permissions::group(true, false, true)
+            | Permissions::other(true, false, true);
+        assert_eq!(perm.mode(), 0o755);
+
+        assert_eq!(Permissions::owner(false, true, false).mode(), 0o200);
+        assert_eq!(Permissions::group(false, false, true).mode(), 0o10);
+        assert_eq!(Permissions::other(true, false, false).mode(), 0o4);
+
+        let perm = Permissions::from_mode(0o4755);
+        assert!(perm.is_setuid());
+        assert!(!perm.is_setgid());
+        assert!(!perm.is_sticky());
+
+        assert!(Permissions::from_mode(0o2755).is_setgid());
+        assert!(Permissions::from_mode(0o1777).is_sticky());
+    }
+
+    #[test]
+    fn test_permissions_from_octal_str() {
+        use openssh_sftp_protocol_error::ParsePermissionsError::*;
+
+        let mode = |s| Permissions::from_octal_str(s).map(|perm| perm.mode());
+
+        assert_eq!(mode("755"), Ok(0o755));
+        assert_eq!(mode("0755"), Ok(0o755));
+        assert_eq!(mode("0o755"), Ok(0o755));
+        assert_eq!(mode("07777"), Ok(0o7777));
+        assert_eq!(mode("0"), Ok(0));
+
+        assert_eq!(mode(""), Err(Empty));
+        assert_eq!(mode("0o"), Err(Empty));
+        assert_eq!(mode("0758"), Err(InvalidDigit('8')));
+        assert_eq!(mode("-755"), Err(InvalidDigit('-')));
+        assert_eq!(mode("10000"), Err(OutOfRange(0o10000)));
+    }
+
+    #[test]
+    fn test_set_get_filetype() {
+        let mut attrs = FileAttrs::default();
+        attrs.set_permissions(Permissions::READ_BY_OWNER);
+        attrs.set_filetype(FileType::Symlink);
+
+        assert_eq!(attrs.get_filetype().unwrap(), FileType::Symlink);
+        assert_eq!(attrs.get_permissions().unwrap(), Permissions::READ_BY_OWNER);
+
+        attrs.set_filetype(FileType::RegularFile);
+        attrs.set_permissions(Permissions::WRITE_BY_OWNER);
+        assert_eq!(attrs.get_filetype().unwrap(), FileType::RegularFile);
+        assert_eq!(
+            attrs.get_permissions().unwrap(),
+            Permissions::WRITE_BY_OWNER
+        );
+
+        let bytes = ssh_format::to_bytes(&attrs).unwrap();
+        let (deserialized, _) = ssh_format::from_bytes::<FileAttrs>(&bytes[4..]).unwrap();
+        assert_eq!(deserialized, attrs);
+    }
+
     #[test]
     fn test_set_get_time() {
         let (atime, mtime) = get_unix_timestamps();
@@ -464,9 +1413,234 @@ mod tests {
         assert_eq!(attrs.get_time().unwrap(), (atime, mtime));
     }
 
+    #[test]
+    fn test_merge() {
+        let (atime, mtime) = get_unix_timestamps();
+
+        let mut base = FileAttrs::builder()
+            .size(2333)
+            .id(1000, 1000)
+            .permissions(Permissions::READ_BY_OWNER)
+            .time(atime, mtime)
+            .build();
+        base.set_filetype(FileType::RegularFile);
+
+        let overlay = FileAttrs::builder()
+            .size(0)
+            .permissions(Permissions::WRITE_BY_OWNER)
+            .build();
+
+        base.merge(&overlay);
+
+        assert_eq!(base.get_size(), Some(0));
+        assert_eq!(base.get_id(), Some((1000, 1000)));
+        assert_eq!(base.get_permissions(), Some(Permissions::WRITE_BY_OWNER));
+        assert_eq!(base.get_filetype(), Some(FileType::RegularFile));
+        assert_eq!(base.get_time(), Some((atime, mtime)));
+
+        let mut empty = FileAttrs::new();
+        empty.merge(&FileAttrs::new());
+        assert_eq!(empty, FileAttrs::new());
+    }
+
+    #[test]
+    fn test_clear() {
+        let (atime, mtime) = get_unix_timestamps();
+
+        let mut attrs = FileAttrs::builder()
+            .size(2333)
+            .id(u32::MAX, 1000)
+            .permissions(Permissions::READ_BY_OWNER)
+            .time(atime, mtime)
+            .build();
+
+        attrs.clear_size();
+        attrs.clear_id();
+        attrs.clear_time();
+
+        assert_eq!(attrs.get_size(), None);
+        assert_eq!(attrs.get_id(), None);
+        assert_eq!(attrs.get_time(), None);
+        assert_eq!(
+            attrs,
+            FileAttrs::builder()
+                .permissions(Permissions::READ_BY_OWNER)
+                .build()
+        );
+
+        attrs.clear_permissions();
+        assert_eq!(attrs.get_permissions(), None);
+        assert_eq!(&ssh_format::to_bytes(&attrs).unwrap()[4..], &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_builder() {
+        let (atime, mtime) = get_unix_timestamps();
+
+        let mut expected = FileAttrs::new();
+        expected.set_size(2333);
+        expected.set_id(u32::MAX, 1000);
+        expected.set_permissions(Permissions::READ_BY_OWNER);
+        expected.set_time(atime, mtime);
+
+        let attrs = FileAttrs::builder()
+            .size(2333)
+            .id(u32::MAX, 1000)
+            .permissions(Permissions::READ_BY_OWNER)
+            .time(atime, mtime)
+            .build();
+
+        assert_eq!(attrs, expected);
+        assert_eq!(
+            ssh_format::to_bytes(&attrs).unwrap(),
+            ssh_format::to_bytes(&expected).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_filetype_longname_char() {
+        for filetype in [
+            FileType::Socket,
+            FileType::Symlink,
+            FileType::RegularFile,
+            FileType::BlockDevice,
+            FileType::Directory,
+            FileType::CharacterDevice,
+            FileType::FIFO,
+        ] {
+            let c = filetype.to_longname_char();
+            assert_eq!(FileType::from_longname_char(c), Some(filetype));
+        }
+
+        assert_eq!(FileType::Directory.to_longname_char(), 'd');
+        assert_eq!(FileType::RegularFile.to_longname_char(), '-');
+        assert_eq!(FileType::from_longname_char('x'), None);
+    }
+
+    #[test]
+    fn test_filetype_display_and_predicates() {
+        assert_eq!(FileType::Directory.to_string(), "d");
+        assert_eq!(FileType::RegularFile.to_string(), "-");
+        assert_eq!(FileType::Symlink.to_string(), "l");
+        assert_eq!(FileType::CharacterDevice.to_string(), "c");
+        assert_eq!(FileType::BlockDevice.to_string(), "b");
+        assert_eq!(FileType::FIFO.to_string(), "p");
+        assert_eq!(FileType::Socket.to_string(), "s");
+
+        assert!(FileType::Directory.is_dir());
+        assert!(!FileType::Directory.is_file());
+        assert!(FileType::RegularFile.is_file());
+        assert!(FileType::Symlink.is_symlink());
+        assert!(FileType::FIFO.is_fifo());
+        assert!(FileType::Socket.is_socket());
+        assert!(FileType::BlockDevice.is_block_device());
+        assert!(FileType::CharacterDevice.is_char_device());
+        assert!(!FileType::CharacterDevice.is_block_device());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_filetype_from_std() {
+        let filetype = |path| FileType::from_std(std::fs::metadata(path).unwrap().file_type());
+
+        assert_eq!(filetype("/"), Some(FileType::Directory));
+        assert_eq!(filetype("Cargo.toml"), Some(FileType::RegularFile));
+        assert_eq!(filetype("/dev/null"), Some(FileType::CharacterDevice));
+    }
+
+    #[test]
+    fn test_filetype_mode() {
+        assert_eq!(FileType::from_mode(0o40755), Some(FileType::Directory));
+        assert_eq!(FileType::from_mode(0o100644), Some(FileType::RegularFile));
+        assert_eq!(FileType::from_mode(0o755), None);
+        assert_eq!(FileType::from_mode(0o170000), None);
+
+        assert_eq!(FileType::Symlink.to_mode(), 0o120000);
+        assert_eq!(FileType::Symlink.to_mode() & !S_IFMT, 0);
+    }
+
+    #[test]
+    fn test_from_type_and_permissions() {
+        let perm = Permissions::READ_BY_OWNER | Permissions::EXECUTE_BY_OWNER;
+        let attrs = FileAttrs::from_type_and_permissions(FileType::Directory, perm);
+
+        assert_eq!(attrs.get_filetype().unwrap(), FileType::Directory);
+        assert_eq!(attrs.get_permissions().unwrap(), perm);
+        assert_eq!(attrs.get_size(), None);
+    }
+
+    #[test]
+    fn test_mime_type() {
+        let attrs = FileAttrs {
+            extensions: vec![
+                (b"text-hint"[..].into(), b"\x01"[..].into()),
+                (b"mime-type"[..].into(), b"text/plain"[..].into()),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(attrs.mime_type(), Some("text/plain"));
+        assert_eq!(FileAttrs::default().mime_type(), None);
+    }
+
+    #[test]
+    fn test_attr_capability_probe() {
+        let mut probe = AttrCapabilityProbe::new();
+
+        let mut attrs = FileAttrs::new();
+        attrs.set_size(1);
+        probe.observe(&attrs);
+
+        probe.observe(&FileAttrs::from_type_and_permissions(
+            FileType::Directory,
+            Permissions::READ_BY_OWNER,
+        ));
+
+        assert_eq!(probe.samples(), 2);
+        assert_eq!(
+            probe.flags(),
+            SSH_FILEXFER_ATTR_SIZE | SSH_FILEXFER_ATTR_PERMISSIONS
+        );
+        assert!(probe.has(SSH_FILEXFER_ATTR_SIZE | SSH_FILEXFER_ATTR_PERMISSIONS));
+        assert!(!probe.has(SSH_FILEXFER_ATTR_SIZE | SSH_FILEXFER_ATTR_UIDGID));
+        assert!(probe.has_filetype());
+
+        probe.observe(&FileAttrs {
+            extensions: vec![(b"mime-type"[..].into(), b"text/plain"[..].into())],
+            ..Default::default()
+        });
+        assert!(probe.has(SSH_FILEXFER_ATTR_EXTENDED));
+    }
+
+    #[test]
+    fn test_flags_wire() {
+        let mut bytes = (SSH_FILEXFER_ATTR_SIZE | SSH_FILEXFER_ATTR_EXTENDED)
+            .to_be_bytes()
+            .to_vec();
+        bytes.extend_from_slice(&2333_u64.to_be_bytes());
+        bytes.extend_from_slice(&1_u32.to_be_bytes());
+        bytes.extend_from_slice(b"\0\0\0\x01a\0\0\0\x01b");
+
+        let (attrs, _) = ssh_format::from_bytes::<FileAttrs>(&bytes).unwrap();
+        assert_eq!(
+            attrs.flags_wire(),
+            SSH_FILEXFER_ATTR_SIZE | SSH_FILEXFER_ATTR_EXTENDED
+        );
+
+        assert_eq!(FileAttrs::new().flags_wire(), 0);
+    }
+
     // Test Serialize and Deserialize
 
-    use serde_test::{assert_de_tokens, assert_tokens, Token};
+    use serde_test::{assert_de_tokens, assert_ser_tokens, assert_tokens, Token};
+
+    struct SerializeSubset<'a>(&'a FileAttrs, u32);
+
+    impl serde::Serialize for SerializeSubset<'_> {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            self.0.serialize_subset(serializer, self.1)
+        }
+    }
 
     #[test]
     fn test_file_attr_flags() {
@@ -481,6 +1655,10 @@ mod tests {
             &FileAttrsFlags::TIME,
             &[Token::U32(SSH_FILEXFER_ATTR_ACMODTIME)],
         );
+        assert_tokens(
+            &FileAttrsFlags::EXTENSIONS,
+            &[Token::U32(SSH_FILEXFER_ATTR_EXTENDED)],
+        );
     }
 
     fn init_attrs(f: impl FnOnce(&mut FileAttrs)) -> FileAttrs {
@@ -558,6 +1736,85 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_serialize_subset() {
+        let (atime, mtime) = get_unix_timestamps();
+
+        let attrs = init_attrs(|attrs| {
+            attrs.set_size(2333);
+            attrs.set_permissions(Permissions::READ_BY_OWNER);
+            attrs.set_time(atime, mtime);
+        });
+
+        assert_ser_tokens(
+            &SerializeSubset(
+                &attrs,
+                SSH_FILEXFER_ATTR_PERMISSIONS | SSH_FILEXFER_ATTR_UIDGID,
+            ),
+            &[
+                Token::Tuple { len: 1 },
+                Token::U32(SSH_FILEXFER_ATTR_PERMISSIONS),
+                Token::U32(Permissions::READ_BY_OWNER.bits()),
+                Token::TupleEnd,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_ser_de_extensions() {
+        let mut attrs = FileAttrs::default();
+        attrs.set_size(2333);
+
+        // No extensions, so the flag is not set
+        let bytes = ssh_format::to_bytes(&attrs).unwrap();
+        assert_eq!(&bytes[4..8], &SSH_FILEXFER_ATTR_SIZE.to_be_bytes());
+
+        attrs.set_extensions(vec![
+            (b"ctime@example.com"[..].into(), b"\0\x01"[..].into()),
+            (b"mime-type"[..].into(), b"text/plain"[..].into()),
+        ]);
+
+        let bytes = ssh_format::to_bytes(&attrs).unwrap();
+        assert_eq!(
+            &bytes[4..8],
+            &(SSH_FILEXFER_ATTR_SIZE | SSH_FILEXFER_ATTR_EXTENDED).to_be_bytes()
+        );
+
+        let (deserialized, rest) = ssh_format::from_bytes::<FileAttrs>(&bytes[4..]).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(deserialized, attrs);
+        assert_eq!(deserialized.get_extensions(), attrs.get_extensions());
+        assert_eq!(deserialized.mime_type(), Some("text/plain"));
+
+        // Extensions are excluded if not in mask
+        let bytes = ssh_format::to_bytes(&SerializeSubset(&attrs, SSH_FILEXFER_ATTR_SIZE)).unwrap();
+        assert_eq!(bytes.len(), 4 + 4 + 8);
+    }
+
+    #[test]
+    fn test_de_extensions_too_long() {
+        let encode = |name_len: usize, data_len: usize| {
+            let mut bytes = SSH_FILEXFER_ATTR_EXTENDED.to_be_bytes().to_vec();
+            bytes.extend_from_slice(&1_u32.to_be_bytes());
+            for len in [name_len, data_len].iter().copied() {
+                bytes.extend_from_slice(&(len as u32).to_be_bytes());
+                bytes.resize(bytes.len() + len, b'a');
+            }
+            bytes
+        };
+
+        let bytes = encode(MAX_EXTENSION_NAME_LEN, MAX_EXTENSION_DATA_LEN);
+        let (attrs, rest) = ssh_format::from_bytes::<FileAttrs>(&bytes).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(attrs.get_extensions().len(), 1);
+
+        let bytes = encode(MAX_EXTENSION_NAME_LEN + 1, 0);
+        ssh_format::from_bytes::<FileAttrs>(&bytes).unwrap_err();
+
+        let bytes = encode(1, MAX_EXTENSION_DATA_LEN + 1);
+        ssh_format::from_bytes::<FileAttrs>(&bytes).unwrap_err();
+    }
+
     #[test]
     fn test_ser_de_all() {
         let (atime, mtime) = get_unix_timestamps();
@@ -1,15 +1,47 @@
 use std::{
     borrow::{Borrow, ToOwned},
+    cmp::Ordering,
     convert::AsRef,
+    fmt,
+    hash::{Hash, Hasher},
     ops::Deref,
 };
 
 use vec_strings::SmallArrayBox;
 
-#[derive(Debug, Clone)]
+use crate::debug::DebugBytes;
+
+#[derive(Clone)]
 #[repr(transparent)]
 pub struct HandleOwned(pub(crate) SmallArrayBox<u8, 4>);
 
+impl HandleOwned {
+    /// Create a handle from `bytes`, e.g. for a server minting handles.
+    ///
+    /// Handles longer than [`Handle::MAX_LEN`] would be rejected by the
+    /// client.
+    pub fn new<I>(bytes: I) -> Self
+    where
+        I: IntoIterator<Item = u8>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        Self(SmallArrayBox::new(bytes))
+    }
+
+    /// Create a handle by copying `bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::new(bytes.iter().copied())
+    }
+}
+
+impl fmt::Debug for HandleOwned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("HandleOwned")
+            .field(&DebugBytes(self.into_inner()))
+            .finish()
+    }
+}
+
 impl Deref for HandleOwned {
     type Target = Handle;
 
@@ -30,20 +62,214 @@ impl AsRef<Handle> for HandleOwned {
     }
 }
 
-#[derive(Debug, Eq, PartialEq, serde::Serialize)]
+/// Compare and hash the same way as [`Handle`], as required by
+/// [`Borrow<Handle>`], so that maps keyed by `HandleOwned` can be looked
+/// up by `&Handle`.
+impl PartialEq for HandleOwned {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for HandleOwned {}
+
+impl Hash for HandleOwned {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl PartialOrd for HandleOwned {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HandleOwned {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+#[derive(Eq, PartialEq, Hash, PartialOrd, Ord, serde::Serialize)]
 #[repr(transparent)]
 pub struct Handle([u8]);
 
+impl PartialEq<Handle> for HandleOwned {
+    fn eq(&self, other: &Handle) -> bool {
+        **self == *other
+    }
+}
+
+impl PartialEq<HandleOwned> for Handle {
+    fn eq(&self, other: &HandleOwned) -> bool {
+        *self == **other
+    }
+}
+
+impl PartialEq<[u8]> for Handle {
+    fn eq(&self, other: &[u8]) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<[u8]> for HandleOwned {
+    fn eq(&self, other: &[u8]) -> bool {
+        **self == *other
+    }
+}
+
+impl fmt::Debug for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Handle")
+            .field(&DebugBytes(self.into_inner()))
+            .finish()
+    }
+}
+
 impl Handle {
+    /// Maximum length of a handle according to the spec.
+    ///
+    /// Longer handles are rejected when decoding
+    /// [`crate::response::ResponseInner::Handle`].
+    pub const MAX_LEN: usize = 256;
+
     pub const fn into_inner(&self) -> &[u8] {
         &self.0
     }
+
+    /// Return true if both handles have the same bytes.
+    ///
+    /// Handle equality is only meaningful within the lifetime of a single
+    /// open: sftp-server may reuse the bytes of a handle once it is closed,
+    /// so two handles from different opens might still compare equal.
+    ///
+    /// Use [`OpenToken`] if you need to tell different opens apart.
+    pub fn bytes_eq(&self, other: &Handle) -> bool {
+        self.0 == other.0
+    }
+
+    /// Compare the handles in constant time, for servers that use
+    /// handles as capability tokens.
+    ///
+    /// Only the length of the handles might be leaked.
+    ///
+    /// Use `==` or [`Handle::bytes_eq`] if timing does not matter.
+    #[cfg(feature = "subtle")]
+    pub fn ct_eq(&self, other: &Handle) -> bool {
+        subtle::ConstantTimeEq::ct_eq(&self.0, &other.0).into()
+    }
 }
 
 impl ToOwned for Handle {
     type Owned = HandleOwned;
 
     fn to_owned(&self) -> Self::Owned {
-        HandleOwned(SmallArrayBox::new(self.into_inner().iter().copied()))
+        HandleOwned::from_bytes(self.into_inner())
+    }
+}
+
+/// [`HandleOwned`] tagged with a generation assigned by the client,
+/// e.g. from a counter that is incremented on every open.
+///
+/// Unlike [`Handle::bytes_eq`], two `OpenToken`s from different opens
+/// never compare equal even if the server reuses the handle.
+#[derive(Debug, Clone)]
+pub struct OpenToken {
+    handle: HandleOwned,
+    generation: u64,
+}
+
+impl OpenToken {
+    pub const fn new(handle: HandleOwned, generation: u64) -> Self {
+        Self { handle, generation }
+    }
+
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    pub const fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn into_handle(self) -> HandleOwned {
+        self.handle
+    }
+}
+
+impl PartialEq for OpenToken {
+    fn eq(&self, other: &Self) -> bool {
+        self.generation == other.generation && self.handle.bytes_eq(&other.handle)
+    }
+}
+
+impl Eq for OpenToken {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handle_owned_new() {
+        let handle = HandleOwned::new(*b"1234");
+        assert_eq!(handle.into_inner(), b"1234");
+        assert_eq!(*HandleOwned::from_bytes(b"1234"), *handle);
+        assert_eq!(*(*handle).to_owned(), *handle);
+    }
+
+    #[test]
+    fn test_handle_cross_type_eq() {
+        let owned = HandleOwned::new(*b"1234");
+        let other = HandleOwned::new(*b"5678");
+        let handle: &Handle = &owned;
+
+        assert!(owned == *handle);
+        assert!(*handle == owned);
+        assert!(other != *handle);
+        assert!(*handle != other);
+
+        assert!(*handle == b"1234"[..]);
+        assert!(owned == b"1234"[..]);
+        assert!(owned != b"5678"[..]);
+    }
+
+    #[test]
+    fn test_handle_as_key() {
+        use std::collections::{BTreeMap, HashMap};
+
+        let handles = [HandleOwned::new(*b"2"), HandleOwned::new(*b"1")];
+
+        let map: HashMap<_, _> = handles.iter().cloned().zip(0..).collect();
+        assert_eq!(map.get(&*HandleOwned::new(*b"1")), Some(&1));
+        assert_eq!(map[&handles[0]], 0);
+
+        let map: BTreeMap<_, _> = handles.iter().cloned().zip(0..).collect();
+        assert_eq!(map.get(&*HandleOwned::new(*b"2")), Some(&0));
+        assert_eq!(map.keys().next(), Some(&handles[1]));
+    }
+
+    #[cfg(feature = "subtle")]
+    #[test]
+    fn test_handle_ct_eq() {
+        let handle = HandleOwned::new(*b"1234");
+
+        assert!(handle.ct_eq(&HandleOwned::new(*b"1234")));
+        assert!(!handle.ct_eq(&HandleOwned::new(*b"1235")));
+        assert!(!handle.ct_eq(&HandleOwned::new(*b"123")));
+        assert!(!handle.ct_eq(&HandleOwned::new(*b"12345")));
+    }
+
+    #[test]
+    fn test_open_token() {
+        let token = OpenToken::new(HandleOwned::new(*b"1234"), 1);
+
+        assert_eq!(token, OpenToken::new(HandleOwned::new(*b"1234"), 1));
+        assert_ne!(token, OpenToken::new(HandleOwned::new(*b"1234"), 2));
+        assert_ne!(token, OpenToken::new(HandleOwned::new(*b"5678"), 1));
+
+        assert!(token.handle().bytes_eq(&HandleOwned::new(*b"1234")));
+        assert_eq!(token.generation(), 1);
+        assert_eq!(token.into_handle().into_inner(), b"1234");
     }
 }
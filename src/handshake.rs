@@ -0,0 +1,107 @@
+#![forbid(unsafe_code)]
+
+use super::{
+    constants, extensions,
+    request::Hello,
+    response::{Extensions, ServerVersion},
+};
+
+/// Helper for the startup sequence of a sftp session.
+///
+/// The client first sends [`Hello`] (`SSH_FXP_INIT`), then reads in
+/// [`ServerVersion`] (`SSH_FXP_VERSION`) and, if the server supports it,
+/// sends [`crate::request::RequestInner::Limits`] to learn about the limits
+/// of the server before issuing any other request.
+#[derive(Debug, Copy, Clone)]
+pub struct Handshake {
+    version: u32,
+}
+
+impl Default for Handshake {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Handshake {
+    /// Create a handshake requesting [`constants::SSH2_FILEXFER_VERSION`].
+    pub const fn new() -> Self {
+        Self::with_version(constants::SSH2_FILEXFER_VERSION)
+    }
+
+    pub const fn with_version(version: u32) -> Self {
+        Self { version }
+    }
+
+    pub const fn version(self) -> u32 {
+        self.version
+    }
+
+    pub fn hello(self) -> Hello {
+        Hello::new(self.version)
+    }
+
+    /// Return [`Hello`] advertising the client-side `extensions`.
+    pub fn hello_with_extensions(self, extensions: &extensions::Extensions) -> Hello {
+        self.hello().with_extensions(extensions)
+    }
+
+    /// Return the serialized [`Hello`] (including the 4-byte size), ready to
+    /// be sent to the server.
+    pub fn hello_bytes(self) -> ssh_format::Result<Vec<u8>> {
+        ssh_format::to_bytes(&self.hello())
+    }
+
+    /// Return true if [`crate::request::RequestInner::Limits`] should be sent
+    /// after receiving `server_version`.
+    pub fn should_request_limits(self, server_version: &ServerVersion) -> bool {
+        server_version.extensions.contains(Extensions::LIMITS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hello_bytes() {
+        assert_eq!(
+            Handshake::new().hello_bytes().unwrap(),
+            [0, 0, 0, 5, constants::SSH_FXP_INIT, 0, 0, 0, 3],
+        );
+    }
+
+    #[test]
+    fn test_hello_with_extensions() {
+        let mut extensions = extensions::Extensions::new();
+        extensions.add_extension("a@example.com", "1");
+
+        let hello = Handshake::new().hello_with_extensions(&extensions);
+        assert_eq!(hello.version, constants::SSH2_FILEXFER_VERSION);
+        assert_eq!(hello.extensions, extensions);
+
+        let mut expected = vec![constants::SSH_FXP_INIT, 0, 0, 0, 3];
+        expected.extend_from_slice(&13_u32.to_be_bytes());
+        expected.extend_from_slice(b"a@example.com");
+        expected.extend_from_slice(&1_u32.to_be_bytes());
+        expected.extend_from_slice(b"1");
+
+        assert_eq!(ssh_format::to_bytes(&hello).unwrap()[4..], expected);
+    }
+
+    #[test]
+    fn test_should_request_limits() {
+        let handshake = Handshake::new();
+
+        let mut server_version = ServerVersion {
+            version: 3,
+            extensions: Extensions::FSYNC,
+            supported: None,
+            raw_extensions: Vec::new(),
+        };
+        assert!(!handshake.should_request_limits(&server_version));
+
+        server_version.extensions |= Extensions::LIMITS;
+        assert!(handshake.should_request_limits(&server_version));
+    }
+}
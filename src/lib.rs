@@ -7,11 +7,16 @@ pub use openssh_sftp_protocol_error::*;
 mod handle;
 pub use handle::*;
 
+mod debug;
 mod seq_iter;
 mod visitor;
 
 pub mod constants;
+pub mod extensions;
 pub mod file_attrs;
+pub mod handshake;
 pub mod open_options;
+pub mod pending_requests;
+pub mod readdir;
 pub mod request;
 pub mod response;
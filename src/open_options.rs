@@ -4,11 +4,25 @@ use super::{constants, file_attrs::FileAttrs, request::OpenFileRequest};
 
 use std::{borrow::Cow, path::Path};
 
-#[derive(Debug, Copy, Clone)]
+/// Options for opening a remote file, similar to [`std::fs::OpenOptions`].
+///
+/// [`std::fs::OpenOptions`] does not expose its flags, so they have to be
+/// mapped manually:
+///
+///  - `read`, `write`, `append` and `create_new` map to the methods with
+///    the same names.
+///  - `create(true)` maps to [`OpenOptions::create_if_missing`].
+///  - `create(true).truncate(true)` maps to [`OpenOptions::truncate`].
+///  - `truncate(true)` without `create(true)` has no equivalent, since
+///    sftp requires the file to be created if it is truncated.
+#[derive(Debug, Default, Copy, Clone)]
 pub struct OpenOptions {
     read: bool,
     write: bool,
     append: bool,
+    create_if_missing: bool,
+    truncate: bool,
+    create_new: bool,
 }
 
 impl OpenOptions {
@@ -17,6 +31,9 @@ impl OpenOptions {
             read: false,
             write: false,
             append: false,
+            create_if_missing: false,
+            truncate: false,
+            create_new: false,
         }
     }
 
@@ -47,7 +64,67 @@ impl OpenOptions {
         self.append
     }
 
+    /// Create the file if it does not exist, otherwise open it as is.
+    pub const fn create_if_missing(mut self, create_if_missing: bool) -> Self {
+        self.create_if_missing = create_if_missing;
+        self
+    }
+
+    pub const fn get_create_if_missing(self) -> bool {
+        self.create_if_missing
+    }
+
+    /// Create the file if it does not exist, otherwise truncate it to zero
+    /// length.
+    pub const fn truncate(mut self, truncate: bool) -> Self {
+        self.truncate = truncate;
+        self
+    }
+
+    pub const fn get_truncate(self) -> bool {
+        self.truncate
+    }
+
+    /// Create the file, fail if it already exists.
+    pub const fn create_new(mut self, create_new: bool) -> Self {
+        self.create_new = create_new;
+        self
+    }
+
+    pub const fn get_create_new(self) -> bool {
+        self.create_new
+    }
+
     pub const fn open(self, filename: Cow<'_, Path>) -> OpenFileRequest<'_> {
+        OpenFileRequest {
+            filename,
+            flags: self.flags(),
+            attrs: FileAttrs::new(),
+        }
+    }
+
+    pub const fn create(
+        self,
+        filename: Cow<'_, Path>,
+        flags: CreateFlags,
+        attrs: FileAttrs,
+    ) -> OpenFileRequest<'_> {
+        let options = match flags {
+            CreateFlags::None => self,
+            CreateFlags::Trunc => self.truncate(true),
+            CreateFlags::Excl => self.create_new(true),
+        };
+
+        OpenFileRequest {
+            filename,
+            flags: options.flags() | constants::SSH_FXF_CREAT,
+            attrs,
+        }
+    }
+
+    /// Return the `SSH_FXF_*` flags that [`OpenOptions::open`] would put
+    /// into the [`OpenFileRequest`].
+    pub const fn flags(self) -> u32 {
         let mut flags: u32 = 0;
 
         if self.read {
@@ -62,23 +139,19 @@ impl OpenOptions {
             flags |= constants::SSH_FXF_APPEND;
         }
 
-        OpenFileRequest {
-            filename,
-            flags,
-            attrs: FileAttrs::new(),
+        if self.create_if_missing {
+            flags |= constants::SSH_FXF_CREAT;
         }
-    }
 
-    pub const fn create(
-        self,
-        filename: Cow<'_, Path>,
-        flags: CreateFlags,
-        attrs: FileAttrs,
-    ) -> OpenFileRequest<'_> {
-        let mut openfile = self.open(filename);
-        openfile.flags |= constants::SSH_FXF_CREAT | flags as u32;
-        openfile.attrs = attrs;
-        openfile
+        if self.truncate {
+            flags |= constants::SSH_FXF_CREAT | constants::SSH_FXF_TRUNC;
+        }
+
+        if self.create_new {
+            flags |= constants::SSH_FXF_CREAT | constants::SSH_FXF_EXCL;
+        }
+
+        flags
     }
 }
 
@@ -94,3 +167,49 @@ pub enum CreateFlags {
     /// Causes the request to fail if the named file already exists.
     Excl = constants::SSH_FXF_EXCL,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_create_new() {
+        let filename = Path::new("a");
+
+        let options = OpenOptions::new().write(true).truncate(true);
+        let request = options.open(Cow::Borrowed(filename));
+        assert_eq!(request.flags(), options.flags());
+        assert_eq!(
+            request.flags(),
+            constants::SSH_FXF_WRITE | constants::SSH_FXF_CREAT | constants::SSH_FXF_TRUNC
+        );
+
+        let request = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(Cow::Borrowed(filename));
+        assert_eq!(
+            request.flags,
+            constants::SSH_FXF_WRITE | constants::SSH_FXF_CREAT | constants::SSH_FXF_EXCL
+        );
+
+        let request = OpenOptions::new()
+            .write(true)
+            .create_if_missing(true)
+            .open(Cow::Borrowed(filename));
+        assert_eq!(
+            request.flags,
+            constants::SSH_FXF_WRITE | constants::SSH_FXF_CREAT
+        );
+
+        let request = OpenOptions::new().write(true).create(
+            Cow::Borrowed(filename),
+            CreateFlags::Trunc,
+            FileAttrs::new(),
+        );
+        assert_eq!(
+            request.flags,
+            constants::SSH_FXF_WRITE | constants::SSH_FXF_CREAT | constants::SSH_FXF_TRUNC
+        );
+    }
+}
@@ -0,0 +1,187 @@
+#![forbid(unsafe_code)]
+
+use super::{
+    request::{Request, RequestId},
+    response::{Response, ResponseInner},
+    PendingRequestError,
+};
+
+use std::collections::{hash_map::Entry, HashMap};
+
+/// State of requests in flight, keyed by `request_id`, for matching
+/// them with the responses from the server.
+///
+/// Ids can be allocated with [`PendingRequests::allocate`], which never
+/// hands out an id still in flight, even after wrapping around.
+#[derive(Debug, Clone)]
+pub struct PendingRequests<T> {
+    pending: HashMap<u32, T>,
+    next_id: u32,
+}
+
+impl<T> Default for PendingRequests<T> {
+    fn default() -> Self {
+        Self {
+            pending: HashMap::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl<T> PendingRequests<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store `state` for the request with `request_id`, which is about
+    /// to be sent.
+    ///
+    /// Return an error and drop `state` if `request_id` is already pending.
+    pub fn insert(&mut self, request_id: u32, state: T) -> Result<(), PendingRequestError> {
+        match self.pending.entry(request_id) {
+            Entry::Occupied(_) => Err(PendingRequestError::DuplicateRequestId(request_id)),
+            Entry::Vacant(entry) => {
+                entry.insert(state);
+                Ok(())
+            }
+        }
+    }
+
+    /// Allocate a request id that is not pending and store `state` for it.
+    ///
+    /// Ids are allocated sequentially and wrap around after [`u32::MAX`],
+    /// skipping the ones still pending, so an id is only reused once its
+    /// response is received, e.g. by [`PendingRequests::complete`].
+    ///
+    /// Return `None` only if all `2^32` ids are pending.
+    pub fn allocate(&mut self, state: T) -> Option<RequestId> {
+        if self.pending.len() as u64 > u32::MAX as u64 {
+            return None;
+        }
+
+        loop {
+            let request_id = self.next_id;
+            self.next_id = self.next_id.wrapping_add(1);
+
+            if let Entry::Vacant(entry) = self.pending.entry(request_id) {
+                entry.insert(state);
+                break Some(RequestId::from_raw(request_id));
+            }
+        }
+    }
+
+    /// Same as [`PendingRequests::insert`], using the `request_id` of
+    /// `request`.
+    pub fn insert_request(
+        &mut self,
+        request: &Request<'_>,
+        state: T,
+    ) -> Result<(), PendingRequestError> {
+        self.insert(request.request_id, state)
+    }
+
+    /// Remove and return the state of the request with `response_id`.
+    pub fn remove(&mut self, response_id: u32) -> Result<T, PendingRequestError> {
+        self.pending
+            .remove(&response_id)
+            .ok_or(PendingRequestError::UnknownResponseId(response_id))
+    }
+
+    /// Remove the state of the request `response` responds to and return
+    /// it along with the response.
+    pub fn complete<'a>(
+        &mut self,
+        response: Response<'a>,
+    ) -> Result<(T, ResponseInner<'a>), PendingRequestError> {
+        let state = self.remove(response.response_id)?;
+
+        Ok((state, response.response_inner))
+    }
+
+    /// Return true if the request with `request_id` is pending.
+    pub fn contains(&self, request_id: u32) -> bool {
+        self.pending.contains_key(&request_id)
+    }
+
+    /// Return number of requests in flight.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Remove and return the state of all pending requests, e.g. to fail
+    /// them when the connection is closed.
+    pub fn drain(&mut self) -> impl Iterator<Item = (u32, T)> + '_ {
+        self.pending.drain()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{file_attrs::FileAttrs, request::RequestInner};
+
+    use std::{borrow::Cow, path::Path};
+
+    #[test]
+    fn test_pending_requests() {
+        let mut pending = PendingRequests::new();
+
+        let request = Request {
+            request_id: 1,
+            inner: RequestInner::Stat(Cow::Borrowed(Path::new("a"))),
+        };
+        pending.insert_request(&request, "stat").unwrap();
+        pending.insert(2, "lstat").unwrap();
+        assert_eq!(
+            pending.insert(2, "fstat"),
+            Err(PendingRequestError::DuplicateRequestId(2))
+        );
+        assert_eq!(pending.len(), 2);
+        assert!(pending.contains(1));
+
+        let response = Response {
+            response_id: 1,
+            response_inner: ResponseInner::Attrs(FileAttrs::new()),
+        };
+        let (state, response_inner) = pending.complete(response).unwrap();
+        assert_eq!(state, "stat");
+        assert!(matches!(response_inner, ResponseInner::Attrs(_)));
+        assert!(!pending.contains(1));
+
+        assert_eq!(
+            pending.remove(1),
+            Err(PendingRequestError::UnknownResponseId(1))
+        );
+
+        assert_eq!(pending.drain().collect::<Vec<_>>(), [(2, "lstat")]);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn test_allocate() {
+        let mut pending = PendingRequests::new();
+
+        let first = pending.allocate("first").unwrap();
+        let second = pending.allocate("second").unwrap();
+        assert_eq!(first.into_raw(), 0);
+        assert_eq!(u32::from(second), 1);
+        assert_eq!(pending.len(), 2);
+
+        let request = Request::new(second, RequestInner::Stat(Cow::Borrowed(Path::new("a"))));
+        assert_eq!(request.request_id, 1);
+
+        assert_eq!(pending.remove(first.into_raw()), Ok("first"));
+        assert!(pending.contains(second.into_raw()));
+
+        // Wrap around, skipping the id still in flight
+        pending.next_id = u32::MAX;
+        assert_eq!(pending.allocate("a"), Some(RequestId::from_raw(u32::MAX)));
+        assert_eq!(pending.allocate("b"), Some(RequestId::from_raw(0)));
+        assert_eq!(pending.allocate("c"), Some(RequestId::from_raw(2)));
+    }
+}
@@ -0,0 +1,171 @@
+#![forbid(unsafe_code)]
+
+use super::{
+    request::RequestInner,
+    response::{NameEntry, ResponseInner, StatusCode},
+    Handle, HandleOwned,
+};
+
+use std::borrow::Cow;
+
+use openssh_sftp_protocol_error::{ErrMsg, ErrorCode};
+
+/// State machine for listing a directory opened by
+/// [`RequestInner::Opendir`].
+///
+/// The same handle is used for every [`RequestInner::Readdir`] until the
+/// server responds with [`StatusCode::Eof`] or an error, after which the
+/// handle should be closed with [`RequestInner::Close`].
+#[derive(Debug, Clone)]
+pub struct ReaddirSession {
+    handle: HandleOwned,
+    finished: bool,
+}
+
+/// Returned by [`ReaddirSession::on_response`].
+#[derive(Debug)]
+pub enum ReaddirStep<'a> {
+    /// Entries returned by the server, another [`RequestInner::Readdir`]
+    /// should be sent to get the rest of them.
+    Entries(Box<[NameEntry]>),
+
+    /// All entries have been returned.
+    Eof,
+
+    /// Listing is aborted by the server.
+    Failed {
+        err_code: ErrorCode,
+        err_msg: ErrMsg,
+    },
+
+    /// The server responded with something other than
+    /// [`ResponseInner::Name`] or an error status, which violates the
+    /// protocol, so listing is aborted.
+    Unexpected(ResponseInner<'a>),
+}
+
+impl ReaddirSession {
+    /// * `handle` - handle returned for [`RequestInner::Opendir`].
+    pub const fn new(handle: HandleOwned) -> Self {
+        Self {
+            handle,
+            finished: false,
+        }
+    }
+
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    /// Return true if no more [`RequestInner::Readdir`] should be sent.
+    pub const fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Return the [`RequestInner::Readdir`] to send, or `None` if the
+    /// listing is finished.
+    pub fn next_request(&self) -> Option<RequestInner<'_>> {
+        if self.finished {
+            None
+        } else {
+            Some(RequestInner::Readdir(Cow::Borrowed(&self.handle)))
+        }
+    }
+
+    /// Classify the response to the last [`RequestInner::Readdir`].
+    pub fn on_response<'a>(&mut self, response: ResponseInner<'a>) -> ReaddirStep<'a> {
+        let step = match response {
+            ResponseInner::Name(entries) => ReaddirStep::Entries(entries),
+            ResponseInner::Status {
+                status_code: StatusCode::Eof,
+                ..
+            } => ReaddirStep::Eof,
+            ResponseInner::Status {
+                status_code: StatusCode::Failure(err_code),
+                err_msg,
+            } => ReaddirStep::Failed { err_code, err_msg },
+            response => ReaddirStep::Unexpected(response),
+        };
+
+        if !matches!(step, ReaddirStep::Entries(_)) {
+            self.finished = true;
+        }
+
+        step
+    }
+
+    /// Return the handle so that it can be closed.
+    pub fn into_handle(self) -> HandleOwned {
+        self.handle
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::file_attrs::FileAttrs;
+
+    use std::path::Path;
+
+    use vec_strings::SmallArrayBox;
+
+    fn status(status_code: StatusCode) -> ResponseInner<'static> {
+        ResponseInner::Status {
+            status_code,
+            err_msg: ErrMsg::default(),
+        }
+    }
+
+    #[test]
+    fn test_readdir_session() {
+        let mut session = ReaddirSession::new(HandleOwned(SmallArrayBox::new(*b"dir")));
+
+        let entry = NameEntry {
+            filename: Path::new("a").into(),
+            longname: Box::default(),
+            attrs: FileAttrs::new(),
+        };
+
+        for _ in 0..2 {
+            assert!(matches!(
+                session.next_request(),
+                Some(RequestInner::Readdir(handle)) if *handle == *session.handle()
+            ));
+
+            let step = session.on_response(ResponseInner::Name(vec![entry.clone()].into()));
+            assert!(matches!(step, ReaddirStep::Entries(entries) if entries.len() == 1));
+            assert!(!session.is_finished());
+        }
+
+        assert!(matches!(
+            session.on_response(status(StatusCode::Eof)),
+            ReaddirStep::Eof
+        ));
+        assert!(session.is_finished());
+        assert!(session.next_request().is_none());
+        assert_eq!(session.into_handle().into_inner(), &b"dir"[..]);
+    }
+
+    #[test]
+    fn test_readdir_session_failed() {
+        let handle = HandleOwned(SmallArrayBox::new(*b"dir"));
+
+        let mut session = ReaddirSession::new(handle.clone());
+        assert!(matches!(
+            session.on_response(status(StatusCode::Failure(ErrorCode::PermDenied))),
+            ReaddirStep::Failed {
+                err_code: ErrorCode::PermDenied,
+                ..
+            }
+        ));
+        assert!(session.next_request().is_none());
+
+        let mut session = ReaddirSession::new(handle);
+        assert!(matches!(
+            session.on_response(status(StatusCode::Success)),
+            ReaddirStep::Unexpected(_)
+        ));
+        assert!(session.is_finished());
+    }
+}
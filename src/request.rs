@@ -1,24 +1,148 @@
 #![forbid(unsafe_code)]
 
-use super::{constants, file_attrs::FileAttrs, open_options::OpenOptions, Handle};
+use super::{
+    constants,
+    debug::{DebugBytes, DebugPath, DebugStr},
+    extensions,
+    file_attrs::{FileAttrs, Permissions},
+    open_options::OpenOptions,
+    response::{Extensions, Limits, Response, ServerVersion},
+    seq_iter::{from_packet_body, SeqIter},
+    Handle, HandleOwned, RequestError,
+};
 
-use std::{borrow::Cow, path::Path};
+use std::{
+    borrow::Cow,
+    convert::TryInto,
+    fmt,
+    marker::PhantomData,
+    num::NonZeroU64,
+    path::{Path, PathBuf},
+};
 
-use serde::{Serialize, Serializer};
+use bitflags::bitflags;
+use serde::{
+    de::{self, DeserializeSeed, SeqAccess, Unexpected, Visitor},
+    ser::{Error as _, SerializeTuple},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 use ssh_format::SerOutput;
 
 /// Response with `Response::Version`.
+#[derive(Debug, Clone)]
 pub struct Hello {
     pub version: u32,
+
+    /// Extensions advertised by the client, sent after `version`.
+    pub extensions: extensions::Extensions,
+}
+
+impl Hello {
+    /// Create [`Hello`] requesting `version` without advertising any
+    /// extension.
+    pub fn new(version: u32) -> Self {
+        Self {
+            version,
+            extensions: extensions::Extensions::new(),
+        }
+    }
+
+    /// Advertise extension `name` with `data`, see
+    /// [`extensions::Extensions::add_extension`].
+    pub fn with_extension(mut self, name: &str, data: &str) -> Self {
+        self.extensions.add_extension(name, data);
+        self
+    }
+
+    /// Advertise all of `extensions`, overwriting data of extensions
+    /// already added with the same name.
+    pub fn with_extensions(mut self, extensions: &extensions::Extensions) -> Self {
+        self.extensions.merge(extensions);
+        self
+    }
+
+    /// Decode the `SSH_FXP_INIT` from `body`, for implementing sftp server.
+    ///
+    /// * `body` - should not include the initial 4-byte which serves
+    ///   as the length of the whole packet and must contain exactly one
+    ///   packet.
+    ///
+    /// `Hello` does not implement `Deserialize`, since the extensions run
+    /// till the end of the packet, which only this function knows.
+    pub fn decode_body(body: &[u8]) -> ssh_format::Result<Self> {
+        from_packet_body(body, HelloVisitor, "init packet")
+    }
 }
 
 impl Serialize for Hello {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        (constants::SSH_FXP_INIT, self.version).serialize(serializer)
+        // dummy size since ssh_format doesn't care
+        let mut tuple_serializer = serializer.serialize_tuple(1)?;
+
+        tuple_serializer.serialize_element(&constants::SSH_FXP_INIT)?;
+        tuple_serializer.serialize_element(&self.version)?;
+        for (name, data) in self.extensions.iter() {
+            tuple_serializer.serialize_element(name)?;
+            tuple_serializer.serialize_element(data)?;
+        }
+
+        tuple_serializer.end()
+    }
+}
+
+/// Decode the `SSH_FXP_INIT` sent by the client via [`from_packet_body`],
+/// since the extensions run till the end of the packet.
+///
+/// Return an error if there are more than
+/// [`crate::response::ServerVersion::MAX_EXTENSIONS`] extensions.
+struct HelloVisitor;
+
+impl<'de> DeserializeSeed<'de> for HelloVisitor {
+    type Value = Hello;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        // Pass a dummy size here since ssh_format doesn't care
+        deserializer.deserialize_tuple(u32::MAX as usize, self)
+    }
+}
+
+impl<'de> Visitor<'de> for HelloVisitor {
+    type Value = Hello;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "Expects SSH_FXP_INIT, version and extensions")
+    }
+
+    fn visit_seq<V: SeqAccess<'de>>(self, seq: V) -> Result<Self::Value, V::Error> {
+        let mut iter = SeqIter::new(seq);
+
+        let packet_type: u8 = iter.get_next()?;
+        if packet_type != constants::SSH_FXP_INIT {
+            return Err(de::Error::invalid_value(
+                Unexpected::Unsigned(packet_type as u64),
+                &"SSH_FXP_INIT",
+            ));
+        }
+
+        let version = iter.get_next()?;
+        let mut extensions = extensions::Extensions::new();
+
+        while let Some(name) = iter.get_next_optional::<String>()? {
+            if extensions.len() as usize == ServerVersion::MAX_EXTENSIONS {
+                return Err(de::Error::custom("Too many extensions"));
+            }
+
+            let data: String = iter.get_next()?;
+            extensions.add_extension(&name, &data);
+        }
+
+        Ok(Hello {
+            version,
+            extensions,
+        })
     }
 }
 
-#[derive(Debug)]
 pub enum RequestInner<'a> {
     /// The response to this message will be either
     /// [`crate::response::ResponseInner::Handle`] (if the operation is successful) or
@@ -50,11 +174,25 @@ pub enum RequestInner<'a> {
     Remove(Cow<'a, Path>),
 
     /// Responds with a [`crate::response::ResponseInner::Status`] message.
+    ///
+    /// The behavior when `newpath` already exists is undefined: most servers
+    /// (including openssh's) fail the request, use
+    /// [`RequestInner::PosixRename`] to atomically replace `newpath`.
     Rename {
         oldpath: Cow<'a, Path>,
         newpath: Cow<'a, Path>,
     },
 
+    /// Same as [`RequestInner::Rename`], but with `flags`, only available
+    /// in sftp v5 and later.
+    ///
+    /// Responds with a [`crate::response::ResponseInner::Status`] message.
+    RenameWithFlags {
+        oldpath: Cow<'a, Path>,
+        newpath: Cow<'a, Path>,
+        flags: RenameFlags,
+    },
+
     /// Responds with a [`crate::response::ResponseInner::Status`] message.
     Mkdir {
         path: Cow<'a, Path>,
@@ -130,6 +268,66 @@ pub enum RequestInner<'a> {
     /// is canonicalised similarly to [`RequestInner::Realpath`].
     ExpandPath(Cow<'a, Path>),
 
+    /// Responds with extended reply, with payload [`crate::response::StatVfs`].
+    ///
+    /// Extension, only available if it is [`crate::response::Extensions::STATVFS`]
+    /// is returned by [`crate::response::ServerVersion`].
+    Statvfs(Cow<'a, Path>),
+
+    /// Same as [`RequestInner::Statvfs`], except that it takes a handle instead of
+    /// a path.
+    ///
+    /// Responds with extended reply, with payload [`crate::response::StatVfs`].
+    ///
+    /// Extension, only available if it is [`crate::response::Extensions::FSTATVFS`]
+    /// is returned by [`crate::response::ServerVersion`].
+    Fstatvfs(Cow<'a, Handle>),
+
+    /// Return home directory of the user, or of the current user if
+    /// the username is empty.
+    ///
+    /// Responds with [`crate::response::ResponseInner::Name`] with
+    /// exactly one entry, same as [`RequestInner::Realpath`].
+    ///
+    /// Extension, only available if it is
+    /// [`crate::response::Extensions::HOME_DIRECTORY`]
+    /// is returned by [`crate::response::ServerVersion`].
+    HomeDirectory(Cow<'a, str>),
+
+    /// Ask the server to hash `length` bytes (or till EOF if `length` is 0)
+    /// of the file starting at `start_offset`, in blocks of `block_size`
+    /// bytes (or as one block if `block_size` is 0).
+    ///
+    /// `algorithms` is a comma-separated list of hash algorithms, see
+    /// [`HashAlgorithms`] and [`RequestInner::check_file_handle`].
+    ///
+    /// Responds with extended reply, with payload
+    /// [`crate::response::CheckFileResponse`].
+    ///
+    /// Extension, only available if it is
+    /// [`crate::response::Extensions::CHECK_FILE`]
+    /// is returned by [`crate::response::ServerVersion`].
+    CheckFileHandle {
+        handle: Cow<'a, Handle>,
+        algorithms: Cow<'a, str>,
+        start_offset: u64,
+        length: u64,
+        block_size: u32,
+    },
+
+    /// Look up names of users and groups by `uids` and `gids`.
+    ///
+    /// Responds with extended reply, with payload
+    /// [`crate::response::UsersGroupsById`].
+    ///
+    /// Extension, only available if it is
+    /// [`crate::response::Extensions::USERS_GROUPS_BY_ID`]
+    /// is returned by [`crate::response::ServerVersion`].
+    UsersGroupsById {
+        uids: Cow<'a, [u32]>,
+        gids: Cow<'a, [u32]>,
+    },
+
     /// Same response as [`RequestInner::Setstat`].
     ///
     /// Extension, only available if it is [`crate::response::Extensions::lsetstat`]
@@ -153,6 +351,10 @@ pub enum RequestInner<'a> {
 
     /// Responds with a [`crate::response::ResponseInner::Status`] message.
     ///
+    /// Same as `rename(2)`: if `newpath` already exists, it is atomically
+    /// replaced, so that there is no point at which another process
+    /// attempting to access `newpath` will find it missing.
+    ///
     /// Extension, only available if it is [`crate::response::Extensions::posix_rename`]
     /// is returned by [`crate::response::ServerVersion`].
     PosixRename {
@@ -178,8 +380,8 @@ pub enum RequestInner<'a> {
     /// be the same handle as long as the range of data is not overlapping.
     /// This allows data to efficiently be moved within a file.
     ///
-    /// If `data_length` is `0`, this imples data should be read until EOF is
-    /// encountered.
+    /// If `read_data_length` is [`CopyLength::ToEof`], data would be read
+    /// until EOF is encountered.
     ///
     /// There are no protocol restictions on this operation; however, the
     /// server MUST ensure that the user does not exceed quota, etc.  The
@@ -191,12 +393,31 @@ pub enum RequestInner<'a> {
     Cp {
         read_from_handle: Cow<'a, Handle>,
         read_from_offset: u64,
-        read_data_length: u64,
+        read_data_length: CopyLength,
 
         write_to_handle: Cow<'a, Handle>,
         write_to_offset: u64,
     },
 
+    /// Extension not modeled by this crate, e.g. a vendor extension.
+    ///
+    /// `data` is appended after `name` as is, without a length prefix,
+    /// so it must already be encoded as the extension expects, e.g. by
+    /// [`RequestInner::extended`].
+    ///
+    /// Responds as defined by the extension, usually with an extended
+    /// reply, which can be decoded as
+    /// [`crate::response::ResponseInner::ExtendedReply`] or with
+    /// [`crate::response::Response::parse_extended_reply`].
+    ///
+    /// Requests received with an extension name unknown to this crate are
+    /// decoded into this variant by [`Request::decode_body`], while
+    /// `Deserialize` rejects them since it cannot tell where `data` ends.
+    Extended {
+        name: Cow<'a, str>,
+        data: Cow<'a, [u8]>,
+    },
+
     /// The write will extend the file if writing beyond the end of the file.
     ///
     /// It is legal to write way beyond the end of the file, the semantics
@@ -217,163 +438,1388 @@ pub enum RequestInner<'a> {
     },
 }
 
-#[derive(Debug)]
-pub struct Request<'a> {
-    pub request_id: u32,
-    pub inner: RequestInner<'a>,
+/// Convert `cow` into [`Cow::Owned`].
+fn into_owned_cow<T: ToOwned + ?Sized + 'static>(cow: Cow<'_, T>) -> Cow<'static, T> {
+    Cow::Owned(cow.into_owned())
 }
-impl Serialize for Request<'_> {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        use RequestInner::*;
 
-        let request_id = self.request_id;
+impl RequestInner<'_> {
+    /// Clone any borrowed path, handle or data so that the request can
+    /// outlive the buffers it was built from, e.g. to be moved onto
+    /// another task.
+    pub fn into_owned(self) -> RequestInner<'static> {
+        use RequestInner::*;
 
-        match &self.inner {
-            Open(params) => (constants::SSH_FXP_OPEN, request_id, params).serialize(serializer),
-            Close(handle) => (constants::SSH_FXP_CLOSE, request_id, handle).serialize(serializer),
+        match self {
+            Open(params) => Open(params.into_owned()),
+            Close(handle) => Close(into_owned_cow(handle)),
             Read {
                 handle,
                 offset,
                 len,
-            } => (constants::SSH_FXP_READ, request_id, handle, *offset, *len).serialize(serializer),
+            } => Read {
+                handle: into_owned_cow(handle),
+                offset,
+                len,
+            },
+            Remove(path) => Remove(into_owned_cow(path)),
+            Rename { oldpath, newpath } => Rename {
+                oldpath: into_owned_cow(oldpath),
+                newpath: into_owned_cow(newpath),
+            },
+            RenameWithFlags {
+                oldpath,
+                newpath,
+                flags,
+            } => RenameWithFlags {
+                oldpath: into_owned_cow(oldpath),
+                newpath: into_owned_cow(newpath),
+                flags,
+            },
+            Mkdir { path, attrs } => Mkdir {
+                path: into_owned_cow(path),
+                attrs,
+            },
+            Rmdir(path) => Rmdir(into_owned_cow(path)),
+            Opendir(path) => Opendir(into_owned_cow(path)),
+            Readdir(handle) => Readdir(into_owned_cow(handle)),
+            Stat(path) => Stat(into_owned_cow(path)),
+            Lstat(path) => Lstat(into_owned_cow(path)),
+            Fstat(handle) => Fstat(into_owned_cow(handle)),
+            Setstat { path, attrs } => Setstat {
+                path: into_owned_cow(path),
+                attrs,
+            },
+            Fsetstat { handle, attrs } => Fsetstat {
+                handle: into_owned_cow(handle),
+                attrs,
+            },
+            Readlink(path) => Readlink(into_owned_cow(path)),
+            Symlink {
+                linkpath,
+                targetpath,
+            } => Symlink {
+                linkpath: into_owned_cow(linkpath),
+                targetpath: into_owned_cow(targetpath),
+            },
+            Realpath(path) => Realpath(into_owned_cow(path)),
+            Limits => Limits,
+            ExpandPath(path) => ExpandPath(into_owned_cow(path)),
+            Statvfs(path) => Statvfs(into_owned_cow(path)),
+            Fstatvfs(handle) => Fstatvfs(into_owned_cow(handle)),
+            HomeDirectory(username) => HomeDirectory(into_owned_cow(username)),
+            CheckFileHandle {
+                handle,
+                algorithms,
+                start_offset,
+                length,
+                block_size,
+            } => CheckFileHandle {
+                handle: into_owned_cow(handle),
+                algorithms: into_owned_cow(algorithms),
+                start_offset,
+                length,
+                block_size,
+            },
+            UsersGroupsById { uids, gids } => UsersGroupsById {
+                uids: into_owned_cow(uids),
+                gids: into_owned_cow(gids),
+            },
+            Lsetstat(path, attrs) => Lsetstat(into_owned_cow(path), attrs),
+            Fsync(handle) => Fsync(into_owned_cow(handle)),
+            HardLink { oldpath, newpath } => HardLink {
+                oldpath: into_owned_cow(oldpath),
+                newpath: into_owned_cow(newpath),
+            },
+            PosixRename { oldpath, newpath } => PosixRename {
+                oldpath: into_owned_cow(oldpath),
+                newpath: into_owned_cow(newpath),
+            },
+            Cp {
+                read_from_handle,
+                read_from_offset,
+                read_data_length,
+                write_to_handle,
+                write_to_offset,
+            } => Cp {
+                read_from_handle: into_owned_cow(read_from_handle),
+                read_from_offset,
+                read_data_length,
+                write_to_handle: into_owned_cow(write_to_handle),
+                write_to_offset,
+            },
+            Extended { name, data } => Extended {
+                name: into_owned_cow(name),
+                data: into_owned_cow(data),
+            },
+            Write {
+                handle,
+                offset,
+                data,
+            } => Write {
+                handle: into_owned_cow(handle),
+                offset,
+                data: into_owned_cow(data),
+            },
+        }
+    }
 
-            Remove(filename) => {
-                (constants::SSH_FXP_REMOVE, request_id, filename).serialize(serializer)
-            }
+    /// Return all paths in the request.
+    fn paths(&self) -> [Option<&Path>; 2] {
+        use RequestInner::*;
 
-            Rename { oldpath, newpath } => {
-                (constants::SSH_FXP_RENAME, request_id, oldpath, newpath).serialize(serializer)
-            }
+        match self {
+            Open(request) => [Some(&request.filename), None],
 
-            Mkdir { path, attrs } => {
-                (constants::SSH_FXP_MKDIR, request_id, path, attrs).serialize(serializer)
+            Remove(path)
+            | Mkdir { path, .. }
+            | Rmdir(path)
+            | Opendir(path)
+            | Stat(path)
+            | Lstat(path)
+            | Setstat { path, .. }
+            | Readlink(path)
+            | Realpath(path)
+            | ExpandPath(path)
+            | Statvfs(path)
+            | Lsetstat(path, _) => [Some(path), None],
+
+            Rename { oldpath, newpath }
+            | RenameWithFlags {
+                oldpath, newpath, ..
             }
+            | HardLink { oldpath, newpath }
+            | PosixRename { oldpath, newpath } => [Some(oldpath), Some(newpath)],
 
-            Rmdir(path) => (constants::SSH_FXP_RMDIR, request_id, path).serialize(serializer),
+            Symlink {
+                linkpath,
+                targetpath,
+            } => [Some(linkpath), Some(targetpath)],
 
-            Opendir(path) => (constants::SSH_FXP_OPENDIR, request_id, path).serialize(serializer),
+            Close(_)
+            | Read { .. }
+            | Readdir(_)
+            | Fstat(_)
+            | Fsetstat { .. }
+            | Limits
+            | Fstatvfs(_)
+            | HomeDirectory(_)
+            | CheckFileHandle { .. }
+            | UsersGroupsById { .. }
+            | Fsync(_)
+            | Cp { .. }
+            | Extended { .. }
+            | Write { .. } => [None, None],
+        }
+    }
 
-            Readdir(handle) => {
-                (constants::SSH_FXP_READDIR, request_id, handle).serialize(serializer)
-            }
+    /// Return the `SSH_FXP_*` packet type the request is serialized to,
+    /// which is [`constants::SSH_FXP_EXTENDED`] for all extensions.
+    pub const fn packet_type(&self) -> u8 {
+        use RequestInner::*;
 
-            Stat(path) => (constants::SSH_FXP_STAT, request_id, path).serialize(serializer),
+        match self {
+            Open(_) => constants::SSH_FXP_OPEN,
+            Close(_) => constants::SSH_FXP_CLOSE,
+            Read { .. } => constants::SSH_FXP_READ,
+            Remove(_) => constants::SSH_FXP_REMOVE,
+            Rename { .. } | RenameWithFlags { .. } => constants::SSH_FXP_RENAME,
+            Mkdir { .. } => constants::SSH_FXP_MKDIR,
+            Rmdir(_) => constants::SSH_FXP_RMDIR,
+            Opendir(_) => constants::SSH_FXP_OPENDIR,
+            Readdir(_) => constants::SSH_FXP_READDIR,
+            Stat(_) => constants::SSH_FXP_STAT,
+            Lstat(_) => constants::SSH_FXP_LSTAT,
+            Fstat(_) => constants::SSH_FXP_FSTAT,
+            Setstat { .. } => constants::SSH_FXP_SETSTAT,
+            Fsetstat { .. } => constants::SSH_FXP_FSETSTAT,
+            Readlink(_) => constants::SSH_FXP_READLINK,
+            Symlink { .. } => constants::SSH_FXP_SYMLINK,
+            Realpath(_) => constants::SSH_FXP_REALPATH,
+            Write { .. } => constants::SSH_FXP_WRITE,
 
-            Lstat(path) => (constants::SSH_FXP_LSTAT, request_id, path).serialize(serializer),
+            Limits
+            | ExpandPath(_)
+            | Statvfs(_)
+            | Fstatvfs(_)
+            | HomeDirectory(_)
+            | CheckFileHandle { .. }
+            | UsersGroupsById { .. }
+            | Lsetstat(..)
+            | Fsync(_)
+            | HardLink { .. }
+            | PosixRename { .. }
+            | Cp { .. }
+            | Extended { .. } => constants::SSH_FXP_EXTENDED,
+        }
+    }
 
-            Fstat(handle) => (constants::SSH_FXP_FSTAT, request_id, handle).serialize(serializer),
+    /// Return name of the extension if the request is an extension.
+    ///
+    /// Return `None` for [`RequestInner::Extended`], whose name is not
+    /// known statically.
+    pub const fn extension_name(&self) -> Option<&'static str> {
+        use RequestInner::*;
 
-            Setstat { path, attrs } => {
-                (constants::SSH_FXP_SETSTAT, request_id, path, attrs).serialize(serializer)
-            }
+        let (name, _version) = match self {
+            Limits => constants::EXT_NAME_LIMITS,
+            ExpandPath(_) => constants::EXT_NAME_EXPAND_PATH,
+            Statvfs(_) => constants::EXT_NAME_STATVFS,
+            Fstatvfs(_) => constants::EXT_NAME_FSTATVFS,
+            HomeDirectory(_) => constants::EXT_NAME_HOME_DIRECTORY,
+            CheckFileHandle { .. } => constants::EXT_NAME_CHECK_FILE_HANDLE,
+            UsersGroupsById { .. } => constants::EXT_NAME_USERS_GROUPS_BY_ID,
+            Lsetstat(..) => constants::EXT_NAME_LSETSTAT,
+            Fsync(_) => constants::EXT_NAME_FSYNC,
+            HardLink { .. } => constants::EXT_NAME_HARDLINK,
+            PosixRename { .. } => constants::EXT_NAME_POSIX_RENAME,
+            Cp { .. } => constants::EXT_NAME_COPY_DATA,
 
-            Fsetstat { handle, attrs } => {
-                (constants::SSH_FXP_FSETSTAT, request_id, handle, attrs).serialize(serializer)
-            }
+            _ => return None,
+        };
 
-            Readlink(path) => (constants::SSH_FXP_READLINK, request_id, path).serialize(serializer),
+        Some(name)
+    }
 
-            Symlink {
-                linkpath,
-                targetpath,
-            } => {
-                (constants::SSH_FXP_SYMLINK, request_id, targetpath, linkpath).serialize(serializer)
-            }
+    /// Return true if the request is an extension.
+    pub const fn is_extension(&self) -> bool {
+        matches!(self, RequestInner::Extended { .. }) || self.extension_name().is_some()
+    }
 
-            Realpath(path) => (constants::SSH_FXP_REALPATH, request_id, path).serialize(serializer),
+    /// Return the extension that must be supported by the server, as
+    /// returned in [`crate::response::ServerVersion::extensions`], before
+    /// the request can be sent.
+    ///
+    /// Return `None` for requests in the base protocol and for
+    /// [`RequestInner::Extended`].
+    pub const fn required_extension(&self) -> Option<Extensions> {
+        use RequestInner::*;
 
-            Limits => (
-                constants::SSH_FXP_EXTENDED,
-                request_id,
-                constants::EXT_NAME_LIMITS.0,
-            )
-                .serialize(serializer),
+        let extension = match self {
+            Limits => Extensions::LIMITS,
+            ExpandPath(_) => Extensions::EXPAND_PATH,
+            Statvfs(_) => Extensions::STATVFS,
+            Fstatvfs(_) => Extensions::FSTATVFS,
+            HomeDirectory(_) => Extensions::HOME_DIRECTORY,
+            CheckFileHandle { .. } => Extensions::CHECK_FILE,
+            UsersGroupsById { .. } => Extensions::USERS_GROUPS_BY_ID,
+            Lsetstat(..) => Extensions::LSETSTAT,
+            Fsync(_) => Extensions::FSYNC,
+            HardLink { .. } => Extensions::HARDLINK,
+            PosixRename { .. } => Extensions::POSIX_RENAME,
+            Cp { .. } => Extensions::COPY_DATA,
 
-            ExpandPath(path) => (
-                constants::SSH_FXP_EXTENDED,
-                request_id,
-                constants::EXT_NAME_EXPAND_PATH.0,
-                path,
-            )
-                .serialize(serializer),
+            _ => return None,
+        };
 
-            Lsetstat(path, attrs) => (
-                constants::SSH_FXP_EXTENDED,
-                request_id,
-                constants::EXT_NAME_LSETSTAT.0,
-                path,
-                attrs,
-            )
-                .serialize(serializer),
+        Some(extension)
+    }
 
-            Fsync(handle) => (
-                constants::SSH_FXP_EXTENDED,
-                request_id,
-                constants::EXT_NAME_FSYNC.0,
-                handle,
-            )
-                .serialize(serializer),
+    /// Check that every path in the request can be sent to the server,
+    /// i.e. it contains no NUL byte.
+    ///
+    /// Serialization does not check this, so a path containing NUL would
+    /// be rejected or silently truncated by the server.
+    pub fn validate(&self) -> Result<(), RequestError> {
+        for path in self.paths().iter().copied().flatten() {
+            if let Some(nul_offset) = find_nul(path) {
+                return Err(RequestError::PathContainsNul {
+                    len: path.as_os_str().len(),
+                    nul_offset,
+                });
+            }
+        }
 
-            HardLink { oldpath, newpath } => (
-                constants::SSH_FXP_EXTENDED,
-                request_id,
-                constants::EXT_NAME_HARDLINK.0,
-                oldpath,
-                newpath,
-            )
-                .serialize(serializer),
+        Ok(())
+    }
+}
 
-            PosixRename { oldpath, newpath } => (
-                constants::SSH_FXP_EXTENDED,
-                request_id,
-                constants::EXT_NAME_POSIX_RENAME.0,
+/// Return offset of the first NUL byte in `path`.
+fn find_nul(path: &Path) -> Option<usize> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        path.as_os_str()
+            .as_bytes()
+            .iter()
+            .position(|byte| *byte == 0)
+    }
+
+    #[cfg(not(unix))]
+    {
+        path.as_os_str().to_string_lossy().find('\0')
+    }
+}
+
+impl fmt::Debug for RequestInner<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use RequestInner::*;
+
+        match self {
+            Open(params) => f.debug_tuple("Open").field(params).finish(),
+            Close(handle) => f.debug_tuple("Close").field(handle).finish(),
+            Read {
+                handle,
+                offset,
+                len,
+            } => f
+                .debug_struct("Read")
+                .field("handle", handle)
+                .field("offset", offset)
+                .field("len", len)
+                .finish(),
+            Remove(path) => f.debug_tuple("Remove").field(&DebugPath(path)).finish(),
+            Rename { oldpath, newpath } => f
+                .debug_struct("Rename")
+                .field("oldpath", &DebugPath(oldpath))
+                .field("newpath", &DebugPath(newpath))
+                .finish(),
+            RenameWithFlags {
                 oldpath,
                 newpath,
-            )
-                .serialize(serializer),
-
+                flags,
+            } => f
+                .debug_struct("RenameWithFlags")
+                .field("oldpath", &DebugPath(oldpath))
+                .field("newpath", &DebugPath(newpath))
+                .field("flags", flags)
+                .finish(),
+            Mkdir { path, attrs } => f
+                .debug_struct("Mkdir")
+                .field("path", &DebugPath(path))
+                .field("attrs", attrs)
+                .finish(),
+            Rmdir(path) => f.debug_tuple("Rmdir").field(&DebugPath(path)).finish(),
+            Opendir(path) => f.debug_tuple("Opendir").field(&DebugPath(path)).finish(),
+            Readdir(handle) => f.debug_tuple("Readdir").field(handle).finish(),
+            Stat(path) => f.debug_tuple("Stat").field(&DebugPath(path)).finish(),
+            Lstat(path) => f.debug_tuple("Lstat").field(&DebugPath(path)).finish(),
+            Fstat(handle) => f.debug_tuple("Fstat").field(handle).finish(),
+            Setstat { path, attrs } => f
+                .debug_struct("Setstat")
+                .field("path", &DebugPath(path))
+                .field("attrs", attrs)
+                .finish(),
+            Fsetstat { handle, attrs } => f
+                .debug_struct("Fsetstat")
+                .field("handle", handle)
+                .field("attrs", attrs)
+                .finish(),
+            Readlink(path) => f.debug_tuple("Readlink").field(&DebugPath(path)).finish(),
+            Symlink {
+                linkpath,
+                targetpath,
+            } => f
+                .debug_struct("Symlink")
+                .field("linkpath", &DebugPath(linkpath))
+                .field("targetpath", &DebugPath(targetpath))
+                .finish(),
+            Realpath(path) => f.debug_tuple("Realpath").field(&DebugPath(path)).finish(),
+            Limits => f.write_str("Limits"),
+            ExpandPath(path) => f.debug_tuple("ExpandPath").field(&DebugPath(path)).finish(),
+            Statvfs(path) => f.debug_tuple("Statvfs").field(&DebugPath(path)).finish(),
+            Fstatvfs(handle) => f.debug_tuple("Fstatvfs").field(handle).finish(),
+            HomeDirectory(username) => f
+                .debug_tuple("HomeDirectory")
+                .field(&DebugStr(username))
+                .finish(),
+            CheckFileHandle {
+                handle,
+                algorithms,
+                start_offset,
+                length,
+                block_size,
+            } => f
+                .debug_struct("CheckFileHandle")
+                .field("handle", handle)
+                .field("algorithms", algorithms)
+                .field("start_offset", start_offset)
+                .field("length", length)
+                .field("block_size", block_size)
+                .finish(),
+            UsersGroupsById { uids, gids } => f
+                .debug_struct("UsersGroupsById")
+                .field("uids", uids)
+                .field("gids", gids)
+                .finish(),
+            Lsetstat(path, attrs) => f
+                .debug_tuple("Lsetstat")
+                .field(&DebugPath(path))
+                .field(attrs)
+                .finish(),
+            Fsync(handle) => f.debug_tuple("Fsync").field(handle).finish(),
+            HardLink { oldpath, newpath } => f
+                .debug_struct("HardLink")
+                .field("oldpath", &DebugPath(oldpath))
+                .field("newpath", &DebugPath(newpath))
+                .finish(),
+            PosixRename { oldpath, newpath } => f
+                .debug_struct("PosixRename")
+                .field("oldpath", &DebugPath(oldpath))
+                .field("newpath", &DebugPath(newpath))
+                .finish(),
             Cp {
                 read_from_handle,
                 read_from_offset,
                 read_data_length,
                 write_to_handle,
                 write_to_offset,
-            } => (
-                constants::SSH_FXP_EXTENDED,
-                request_id,
-                constants::EXT_NAME_COPY_DATA.0,
-                read_from_handle,
-                read_from_offset,
-                read_data_length,
-                write_to_handle,
-                write_to_offset,
-            )
-                .serialize(serializer),
-
+            } => f
+                .debug_struct("Cp")
+                .field("read_from_handle", read_from_handle)
+                .field("read_from_offset", read_from_offset)
+                .field("read_data_length", read_data_length)
+                .field("write_to_handle", write_to_handle)
+                .field("write_to_offset", write_to_offset)
+                .finish(),
             Write {
                 handle,
                 offset,
                 data,
-            } => (constants::SSH_FXP_WRITE, request_id, handle, offset, data).serialize(serializer),
+            } => f
+                .debug_struct("Write")
+                .field("handle", handle)
+                .field("offset", offset)
+                .field("data", &DebugBytes(data))
+                .finish(),
+            Extended { name, data } => f
+                .debug_struct("Extended")
+                .field("name", name)
+                .field("data", &DebugBytes(data))
+                .finish(),
         }
     }
 }
-impl Request<'_> {
-    /// The write will extend the file if writing beyond the end of the file.
-    ///
-    /// It is legal to write way beyond the end of the file, the semantics
-    /// are to write zeroes from the end of the file to the specified offset
-    /// and then the data.
-    ///
-    /// On most operating systems, such writes do not allocate disk space but
-    /// instead leave "holes" in the file.
-    ///
-    /// Responds with a [`crate::response::ResponseInner::Status`] message.
+
+impl<'a> RequestInner<'a> {
+    /// Create [`RequestInner::Read`].
+    pub const fn read_at(handle: Cow<'a, Handle>, offset: u64, len: u32) -> Self {
+        RequestInner::Read {
+            handle,
+            offset,
+            len,
+        }
+    }
+
+    /// Create [`RequestInner::Read`] that reads from the start of the file
+    /// with `size` bytes, capped at `max_read_len`.
     ///
-    /// The Write also includes any amount of custom data and its size is
-    /// included in the size of the entire packet sent.
+    /// If `size` is larger than `max_read_len`, then the caller must send
+    /// more [`RequestInner::Read`] (e.g. using [`RequestInner::read_at`])
+    /// to read the rest of the file.
     ///
-    /// Return the serialized header (including the 4-byte size).
+    /// `max_read_len` is usually [`crate::response::Limits::read_len`].
+    pub fn read_all(handle: Cow<'a, Handle>, size: u64, max_read_len: u32) -> Self {
+        let len = size.min(max_read_len as u64) as u32;
+        Self::read_at(handle, 0, len)
+    }
+
+    /// Create [`RequestInner::RenameWithFlags`].
+    pub const fn rename_with_flags(
+        oldpath: Cow<'a, Path>,
+        newpath: Cow<'a, Path>,
+        flags: RenameFlags,
+    ) -> Self {
+        RequestInner::RenameWithFlags {
+            oldpath,
+            newpath,
+            flags,
+        }
+    }
+
+    /// Create [`RequestInner::Mkdir`] with empty attributes, so that the
+    /// server uses its default mode.
+    pub const fn mkdir(path: Cow<'a, Path>) -> Self {
+        RequestInner::Mkdir {
+            path,
+            attrs: FileAttrs::new(),
+        }
+    }
+
+    /// Create [`RequestInner::Mkdir`] with only `permissions` set in
+    /// the attributes.
+    pub fn mkdir_with_mode(path: Cow<'a, Path>, permissions: Permissions) -> Self {
+        let mut attrs = FileAttrs::new();
+        attrs.set_permissions(permissions);
+
+        RequestInner::Mkdir { path, attrs }
+    }
+
+    /// Create [`RequestInner::CheckFileHandle`] with `algorithms`.
+    pub fn check_file_handle(
+        handle: Cow<'a, Handle>,
+        algorithms: &HashAlgorithms,
+        start_offset: u64,
+        length: u64,
+        block_size: u32,
+    ) -> Self {
+        RequestInner::CheckFileHandle {
+            handle,
+            algorithms: Cow::Owned(algorithms.to_string()),
+            start_offset,
+            length,
+            block_size,
+        }
+    }
+
+    /// Create [`RequestInner::PosixRename`], which atomically replaces
+    /// `newpath` if it exists, unlike [`RequestInner::Rename`].
+    pub const fn posix_rename(oldpath: Cow<'a, Path>, newpath: Cow<'a, Path>) -> Self {
+        RequestInner::PosixRename { oldpath, newpath }
+    }
+
+    /// Create [`RequestInner::Extended`] named `name`, with `operand`
+    /// serialized in ssh format as its data, e.g. `(path,)` for an
+    /// extension taking one path.
+    pub fn extended<T: Serialize>(name: Cow<'a, str>, operand: &T) -> ssh_format::Result<Self> {
+        let mut data = ssh_format::to_bytes(operand)?;
+        // Remove the length header of the whole serialized output
+        data.drain(..4);
+
+        Ok(RequestInner::Extended {
+            name,
+            data: Cow::Owned(data),
+        })
+    }
+
+    /// Split a [`RequestInner::Cp`] into multiple [`RequestInner::Cp`], each
+    /// copying at most `max_len` bytes, with advancing `read_from_offset` and
+    /// `write_to_offset`.
+    ///
+    /// If `read_data_length` is [`CopyLength::ToEof`], then only one
+    /// [`RequestInner::Cp`] that copies until EOF is returned.
+    ///
+    /// Stops early if the offsets of the next chunk would overflow `u64`.
+    pub fn split_cp(
+        read_from_handle: &'a Handle,
+        read_from_offset: u64,
+        read_data_length: CopyLength,
+        write_to_handle: &'a Handle,
+        write_to_offset: u64,
+        max_len: NonZeroU64,
+    ) -> CpChunks<'a> {
+        CpChunks {
+            read_from_handle,
+            read_from_offset,
+            write_to_handle,
+            write_to_offset,
+            remaining: read_data_length.into_raw(),
+            max_len,
+            copy_until_eof: read_data_length == CopyLength::ToEof,
+        }
+    }
+}
+
+/// Iterator returned by [`RequestInner::split_cp`].
+#[derive(Debug, Clone)]
+pub struct CpChunks<'a> {
+    read_from_handle: &'a Handle,
+    read_from_offset: u64,
+    write_to_handle: &'a Handle,
+    write_to_offset: u64,
+    remaining: u64,
+    max_len: NonZeroU64,
+    copy_until_eof: bool,
+}
+
+impl<'a> Iterator for CpChunks<'a> {
+    type Item = RequestInner<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let read_data_length = if self.copy_until_eof {
+            self.copy_until_eof = false;
+            CopyLength::ToEof
+        } else {
+            let len = NonZeroU64::new(self.remaining.min(self.max_len.get()))?;
+            self.remaining -= len.get();
+            CopyLength::Exactly(len)
+        };
+
+        let request = RequestInner::Cp {
+            read_from_handle: Cow::Borrowed(self.read_from_handle),
+            read_from_offset: self.read_from_offset,
+            read_data_length,
+            write_to_handle: Cow::Borrowed(self.write_to_handle),
+            write_to_offset: self.write_to_offset,
+        };
+
+        let len = read_data_length.into_raw();
+        match (
+            self.read_from_offset.checked_add(len),
+            self.write_to_offset.checked_add(len),
+        ) {
+            (Some(read_from_offset), Some(write_to_offset)) => {
+                self.read_from_offset = read_from_offset;
+                self.write_to_offset = write_to_offset;
+            }
+            // There is no offset left for the next chunk
+            _ => self.remaining = 0,
+        }
+
+        Some(request)
+    }
+}
+
+bitflags! {
+    /// Flags of [`RequestInner::RenameWithFlags`].
+    #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+    pub struct RenameFlags: u32 {
+        /// Replace `newpath` if it already exists.
+        const OVERWRITE = constants::SSH_FXF_RENAME_OVERWRITE;
+
+        /// Replace `newpath` atomically, must be used with `OVERWRITE`.
+        const ATOMIC = constants::SSH_FXF_RENAME_ATOMIC;
+
+        /// Use the native rename of the server, which might have
+        /// different semantics.
+        const NATIVE = constants::SSH_FXF_RENAME_NATIVE;
+    }
+}
+
+impl fmt::Display for RenameFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        bitflags::parser::to_writer(self, f)
+    }
+}
+
+impl Serialize for RenameFlags {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.bits().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RenameFlags {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u32::deserialize(deserializer).map(RenameFlags::from_bits_retain)
+    }
+}
+
+/// Number of bytes to copy in [`RequestInner::Cp`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum CopyLength {
+    /// Copy until EOF is encountered.
+    ToEof,
+
+    /// Copy exactly this many bytes, unless EOF is encountered first.
+    Exactly(NonZeroU64),
+}
+
+impl CopyLength {
+    /// Return the value on the wire, where `0` means [`CopyLength::ToEof`].
+    pub const fn into_raw(self) -> u64 {
+        match self {
+            CopyLength::ToEof => 0,
+            CopyLength::Exactly(len) => len.get(),
+        }
+    }
+
+    /// Inverse of [`CopyLength::into_raw`].
+    pub const fn from_raw(len: u64) -> Self {
+        match NonZeroU64::new(len) {
+            Some(len) => CopyLength::Exactly(len),
+            None => CopyLength::ToEof,
+        }
+    }
+}
+
+impl Serialize for CopyLength {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.into_raw().serialize(serializer)
+    }
+}
+
+/// Hash algorithm supported by the check-file extension.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum HashAlgorithm {
+    Md5,
+    Sha1,
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+    Crc32,
+}
+
+impl HashAlgorithm {
+    /// Return name of the algorithm used on the wire.
+    pub const fn name(self) -> &'static str {
+        use HashAlgorithm::*;
+
+        match self {
+            Md5 => "md5",
+            Sha1 => "sha1",
+            Sha224 => "sha224",
+            Sha256 => "sha256",
+            Sha384 => "sha384",
+            Sha512 => "sha512",
+            Crc32 => "crc32",
+        }
+    }
+
+    /// Inverse of [`HashAlgorithm::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        use HashAlgorithm::*;
+
+        [Md5, Sha1, Sha224, Sha256, Sha384, Sha512, Crc32]
+            .iter()
+            .copied()
+            .find(|algorithm| algorithm.name() == name)
+    }
+
+    /// Return length of the hash produced by the algorithm.
+    pub const fn digest_len(self) -> usize {
+        use HashAlgorithm::*;
+
+        match self {
+            Md5 => 16,
+            Sha1 => 20,
+            Sha224 => 28,
+            Sha256 => 32,
+            Sha384 => 48,
+            Sha512 => 64,
+            Crc32 => 4,
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// List of hash algorithms in the check-file request, ordered by
+/// preference.
+///
+/// Serialized as a comma-separated list of names.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash)]
+pub struct HashAlgorithms(Vec<HashAlgorithm>);
+
+impl HashAlgorithms {
+    pub const fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Append `algorithm` to the list, it is ignored if it is already
+    /// in the list.
+    pub fn with(mut self, algorithm: HashAlgorithm) -> Self {
+        if !self.0.contains(&algorithm) {
+            self.0.push(algorithm);
+        }
+        self
+    }
+
+    pub fn as_slice(&self) -> &[HashAlgorithm] {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for HashAlgorithms {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, algorithm) in self.0.iter().enumerate() {
+            if i != 0 {
+                f.write_str(",")?;
+            }
+            f.write_str(algorithm.name())?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for HashAlgorithms {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[derive(Debug)]
+pub struct Request<'a> {
+    pub request_id: u32,
+    pub inner: RequestInner<'a>,
+}
+impl Serialize for Request<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use RequestInner::*;
+
+        let request_id = self.request_id;
+
+        match &self.inner {
+            Open(params) => (constants::SSH_FXP_OPEN, request_id, params).serialize(serializer),
+            Close(handle) => (constants::SSH_FXP_CLOSE, request_id, handle).serialize(serializer),
+            Read {
+                handle,
+                offset,
+                len,
+            } => (constants::SSH_FXP_READ, request_id, handle, *offset, *len).serialize(serializer),
+
+            Remove(filename) => {
+                (constants::SSH_FXP_REMOVE, request_id, filename).serialize(serializer)
+            }
+
+            Rename { oldpath, newpath } => {
+                (constants::SSH_FXP_RENAME, request_id, oldpath, newpath).serialize(serializer)
+            }
+
+            RenameWithFlags {
+                oldpath,
+                newpath,
+                flags,
+            } => (
+                constants::SSH_FXP_RENAME,
+                request_id,
+                oldpath,
+                newpath,
+                flags,
+            )
+                .serialize(serializer),
+
+            Mkdir { path, attrs } => {
+                (constants::SSH_FXP_MKDIR, request_id, path, attrs).serialize(serializer)
+            }
+
+            Rmdir(path) => (constants::SSH_FXP_RMDIR, request_id, path).serialize(serializer),
+
+            Opendir(path) => (constants::SSH_FXP_OPENDIR, request_id, path).serialize(serializer),
+
+            Readdir(handle) => {
+                (constants::SSH_FXP_READDIR, request_id, handle).serialize(serializer)
+            }
+
+            Stat(path) => (constants::SSH_FXP_STAT, request_id, path).serialize(serializer),
+
+            Lstat(path) => (constants::SSH_FXP_LSTAT, request_id, path).serialize(serializer),
+
+            Fstat(handle) => (constants::SSH_FXP_FSTAT, request_id, handle).serialize(serializer),
+
+            Setstat { path, attrs } => {
+                (constants::SSH_FXP_SETSTAT, request_id, path, attrs).serialize(serializer)
+            }
+
+            Fsetstat { handle, attrs } => {
+                (constants::SSH_FXP_FSETSTAT, request_id, handle, attrs).serialize(serializer)
+            }
+
+            Readlink(path) => (constants::SSH_FXP_READLINK, request_id, path).serialize(serializer),
+
+            Symlink {
+                linkpath,
+                targetpath,
+            } => {
+                (constants::SSH_FXP_SYMLINK, request_id, targetpath, linkpath).serialize(serializer)
+            }
+
+            Realpath(path) => (constants::SSH_FXP_REALPATH, request_id, path).serialize(serializer),
+
+            Limits => (
+                constants::SSH_FXP_EXTENDED,
+                request_id,
+                constants::EXT_NAME_LIMITS.0,
+            )
+                .serialize(serializer),
+
+            ExpandPath(path) => (
+                constants::SSH_FXP_EXTENDED,
+                request_id,
+                constants::EXT_NAME_EXPAND_PATH.0,
+                path,
+            )
+                .serialize(serializer),
+
+            Statvfs(path) => (
+                constants::SSH_FXP_EXTENDED,
+                request_id,
+                constants::EXT_NAME_STATVFS.0,
+                path,
+            )
+                .serialize(serializer),
+
+            Fstatvfs(handle) => (
+                constants::SSH_FXP_EXTENDED,
+                request_id,
+                constants::EXT_NAME_FSTATVFS.0,
+                handle,
+            )
+                .serialize(serializer),
+
+            HomeDirectory(username) => (
+                constants::SSH_FXP_EXTENDED,
+                request_id,
+                constants::EXT_NAME_HOME_DIRECTORY.0,
+                username,
+            )
+                .serialize(serializer),
+
+            CheckFileHandle {
+                handle,
+                algorithms,
+                start_offset,
+                length,
+                block_size,
+            } => (
+                constants::SSH_FXP_EXTENDED,
+                request_id,
+                constants::EXT_NAME_CHECK_FILE_HANDLE.0,
+                handle,
+                algorithms,
+                start_offset,
+                length,
+                block_size,
+            )
+                .serialize(serializer),
+
+            UsersGroupsById { uids, gids } => (
+                constants::SSH_FXP_EXTENDED,
+                request_id,
+                constants::EXT_NAME_USERS_GROUPS_BY_ID.0,
+                IdList(uids),
+                IdList(gids),
+            )
+                .serialize(serializer),
+
+            Lsetstat(path, attrs) => (
+                constants::SSH_FXP_EXTENDED,
+                request_id,
+                constants::EXT_NAME_LSETSTAT.0,
+                path,
+                attrs,
+            )
+                .serialize(serializer),
+
+            Fsync(handle) => (
+                constants::SSH_FXP_EXTENDED,
+                request_id,
+                constants::EXT_NAME_FSYNC.0,
+                handle,
+            )
+                .serialize(serializer),
+
+            HardLink { oldpath, newpath } => (
+                constants::SSH_FXP_EXTENDED,
+                request_id,
+                constants::EXT_NAME_HARDLINK.0,
+                oldpath,
+                newpath,
+            )
+                .serialize(serializer),
+
+            PosixRename { oldpath, newpath } => (
+                constants::SSH_FXP_EXTENDED,
+                request_id,
+                constants::EXT_NAME_POSIX_RENAME.0,
+                oldpath,
+                newpath,
+            )
+                .serialize(serializer),
+
+            Cp {
+                read_from_handle,
+                read_from_offset,
+                read_data_length,
+                write_to_handle,
+                write_to_offset,
+            } => (
+                constants::SSH_FXP_EXTENDED,
+                request_id,
+                constants::EXT_NAME_COPY_DATA.0,
+                read_from_handle,
+                read_from_offset,
+                read_data_length,
+                write_to_handle,
+                write_to_offset,
+            )
+                .serialize(serializer),
+
+            Extended { name, data } => {
+                // dummy size since ssh_format doesn't care
+                let mut tuple_serializer = serializer.serialize_tuple(1)?;
+
+                tuple_serializer.serialize_element(&constants::SSH_FXP_EXTENDED)?;
+                tuple_serializer.serialize_element(&request_id)?;
+                tuple_serializer.serialize_element(name)?;
+                // The data is not length-prefixed.
+                for byte in data.iter() {
+                    tuple_serializer.serialize_element(byte)?;
+                }
+
+                tuple_serializer.end()
+            }
+
+            Write {
+                handle,
+                offset,
+                data,
+            } => (constants::SSH_FXP_WRITE, request_id, handle, offset, data).serialize(serializer),
+        }
+    }
+}
+
+/// Maximum length of the data of [`RequestInner::Write`] and of each id
+/// list of [`RequestInner::UsersGroupsById`] accepted on decoding, the
+/// maximum packet length accepted by openssh's sftp-server.
+const MAX_DATA_LEN: usize = Limits::DEFAULT.packet_len as usize;
+
+/// Deserialize a request sent by the client, for implementing sftp server.
+///
+/// Paths and handles are always owned since they are decoded from the
+/// wire, while the data of [`RequestInner::Write`] is borrowed from the
+/// input of the deserializer if possible.
+///
+/// Trailing optional fields, e.g. the flags of
+/// [`RequestInner::RenameWithFlags`], can only be detected by
+/// [`Request::decode_body`], so they are never read here, which keeps
+/// the following packets intact when deserializing from a stream.
+///
+/// Return an error if the data of [`RequestInner::Write`] is longer than
+/// [`Limits::DEFAULT`]`.packet_len`, or for [`RequestInner::Extended`],
+/// whose payload runs till the end of the packet.
+impl<'de: 'a, 'a> Deserialize<'de> for Request<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        RequestVisitor::new(false).deserialize(deserializer)
+    }
+}
+
+struct RequestVisitor<'a> {
+    /// Whether the sequence ends at the end of the packet, i.e. whether
+    /// it is visited via [`from_packet_body`], so that trailing optional
+    /// fields can be read.
+    packet: bool,
+    phantom: PhantomData<Request<'a>>,
+}
+
+impl RequestVisitor<'_> {
+    const fn new(packet: bool) -> Self {
+        Self {
+            packet,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'de: 'a, 'a> DeserializeSeed<'de> for RequestVisitor<'a> {
+    type Value = Request<'a>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        // Pass a dummy size here since ssh_format doesn't care
+        deserializer.deserialize_tuple(u32::MAX as usize, self)
+    }
+}
+
+impl<'de: 'a, 'a> Visitor<'de> for RequestVisitor<'a> {
+    type Value = Request<'a>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "Expects a u8 type and payload")
+    }
+
+    fn visit_seq<V: SeqAccess<'de>>(self, seq: V) -> Result<Self::Value, V::Error> {
+        use constants::*;
+        use RequestInner::*;
+
+        let mut iter = SeqIter::new(seq);
+
+        let discriminant: u8 = iter.get_next()?;
+        let request_id: u32 = iter.get_next()?;
+
+        let inner = match discriminant {
+            SSH_FXP_OPEN => Open(OpenFileRequest {
+                filename: next_path(&mut iter)?,
+                flags: iter.get_next()?,
+                attrs: iter.get_next()?,
+            }),
+            SSH_FXP_CLOSE => Close(next_handle(&mut iter)?),
+            SSH_FXP_READ => Read {
+                handle: next_handle(&mut iter)?,
+                offset: iter.get_next()?,
+                len: iter.get_next()?,
+            },
+            SSH_FXP_WRITE => {
+                let handle = next_handle(&mut iter)?;
+                let offset = iter.get_next()?;
+                let data = iter.get_next::<RequestData<'de>>()?.0;
+
+                if data.len() > MAX_DATA_LEN {
+                    return Err(de::Error::custom(format_args!(
+                        "data of write exceeds the limit of {} bytes",
+                        MAX_DATA_LEN
+                    )));
+                }
+
+                Write {
+                    handle,
+                    offset,
+                    data,
+                }
+            }
+            SSH_FXP_REMOVE => Remove(next_path(&mut iter)?),
+            SSH_FXP_RENAME => {
+                let oldpath = next_path(&mut iter)?;
+                let newpath = next_path(&mut iter)?;
+
+                let flags = if self.packet {
+                    iter.get_next_optional()?
+                } else {
+                    None
+                };
+
+                match flags {
+                    Some(flags) => RenameWithFlags {
+                        oldpath,
+                        newpath,
+                        flags,
+                    },
+                    None => Rename { oldpath, newpath },
+                }
+            }
+            SSH_FXP_MKDIR => Mkdir {
+                path: next_path(&mut iter)?,
+                attrs: iter.get_next()?,
+            },
+            SSH_FXP_RMDIR => Rmdir(next_path(&mut iter)?),
+            SSH_FXP_OPENDIR => Opendir(next_path(&mut iter)?),
+            SSH_FXP_READDIR => Readdir(next_handle(&mut iter)?),
+            SSH_FXP_STAT => Stat(next_path(&mut iter)?),
+            SSH_FXP_LSTAT => Lstat(next_path(&mut iter)?),
+            SSH_FXP_FSTAT => Fstat(next_handle(&mut iter)?),
+            SSH_FXP_SETSTAT => Setstat {
+                path: next_path(&mut iter)?,
+                attrs: iter.get_next()?,
+            },
+            SSH_FXP_FSETSTAT => Fsetstat {
+                handle: next_handle(&mut iter)?,
+                attrs: iter.get_next()?,
+            },
+            SSH_FXP_READLINK => Readlink(next_path(&mut iter)?),
+            SSH_FXP_SYMLINK => {
+                // Same order as openssh, see Serialize
+                let targetpath = next_path(&mut iter)?;
+                let linkpath = next_path(&mut iter)?;
+
+                Symlink {
+                    linkpath,
+                    targetpath,
+                }
+            }
+            SSH_FXP_REALPATH => Realpath(next_path(&mut iter)?),
+            SSH_FXP_EXTENDED => {
+                let name: String = iter.get_next()?;
+
+                match name.as_str() {
+                    name if name == EXT_NAME_LIMITS.0 => Limits,
+                    name if name == EXT_NAME_EXPAND_PATH.0 => ExpandPath(next_path(&mut iter)?),
+                    name if name == EXT_NAME_STATVFS.0 => Statvfs(next_path(&mut iter)?),
+                    name if name == EXT_NAME_FSTATVFS.0 => Fstatvfs(next_handle(&mut iter)?),
+                    name if name == EXT_NAME_HOME_DIRECTORY.0 => {
+                        HomeDirectory(Cow::Owned(iter.get_next()?))
+                    }
+                    name if name == EXT_NAME_CHECK_FILE_HANDLE.0 => CheckFileHandle {
+                        handle: next_handle(&mut iter)?,
+                        algorithms: Cow::Owned(iter.get_next()?),
+                        start_offset: iter.get_next()?,
+                        length: iter.get_next()?,
+                        block_size: iter.get_next()?,
+                    },
+                    name if name == EXT_NAME_USERS_GROUPS_BY_ID.0 => UsersGroupsById {
+                        uids: Cow::Owned(next_id_list(&mut iter)?),
+                        gids: Cow::Owned(next_id_list(&mut iter)?),
+                    },
+                    name if name == EXT_NAME_LSETSTAT.0 => {
+                        Lsetstat(next_path(&mut iter)?, iter.get_next()?)
+                    }
+                    name if name == EXT_NAME_FSYNC.0 => Fsync(next_handle(&mut iter)?),
+                    name if name == EXT_NAME_HARDLINK.0 => HardLink {
+                        oldpath: next_path(&mut iter)?,
+                        newpath: next_path(&mut iter)?,
+                    },
+                    name if name == EXT_NAME_POSIX_RENAME.0 => PosixRename {
+                        oldpath: next_path(&mut iter)?,
+                        newpath: next_path(&mut iter)?,
+                    },
+                    name if name == EXT_NAME_COPY_DATA.0 => Cp {
+                        read_from_handle: next_handle(&mut iter)?,
+                        read_from_offset: iter.get_next()?,
+                        read_data_length: CopyLength::from_raw(iter.get_next()?),
+                        write_to_handle: next_handle(&mut iter)?,
+                        write_to_offset: iter.get_next()?,
+                    },
+                    // Only Request::decode_body knows where the payload ends
+                    name => {
+                        return Err(de::Error::custom(format_args!(
+                            "extended request {} can only be decoded by Request::decode_body",
+                            name
+                        )))
+                    }
+                }
+            }
+
+            _ => {
+                return Err(de::Error::invalid_value(
+                    Unexpected::Unsigned(discriminant as u64),
+                    &"Invalid packet type",
+                ))
+            }
+        };
+
+        Ok(Request { request_id, inner })
+    }
+}
+
+/// Deserialize data of [`RequestInner::Write`] via
+/// [`Response::deserialize_data`].
+struct RequestData<'de>(Cow<'de, [u8]>);
+
+impl<'de> Deserialize<'de> for RequestData<'de> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Response::deserialize_data(deserializer).map(RequestData)
+    }
+}
+
+/// Return true if `name` is the name of an extension decoded into its own
+/// variant of [`RequestInner`] instead of [`RequestInner::Extended`].
+fn is_known_extension(name: &str) -> bool {
+    Extensions::all()
+        .iter()
+        .filter_map(Extensions::name)
+        .any(|known| known == name)
+}
+
+fn next_path<'de, 'a, V: SeqAccess<'de>>(
+    iter: &mut SeqIter<'de, V>,
+) -> Result<Cow<'a, Path>, V::Error> {
+    iter.get_next::<String>()
+        .map(|path| Cow::Owned(PathBuf::from(path)))
+}
+
+fn next_handle<'de, 'a, V: SeqAccess<'de>>(
+    iter: &mut SeqIter<'de, V>,
+) -> Result<Cow<'a, Handle>, V::Error> {
+    iter.get_next_bytes_capped(Handle::MAX_LEN)
+        .map(|handle| Cow::Owned(HandleOwned::from_bytes(&handle)))
+}
+
+/// Inverse of [`IdList`].
+fn next_id_list<'de, V: SeqAccess<'de>>(iter: &mut SeqIter<'de, V>) -> Result<Vec<u32>, V::Error> {
+    let bytes = iter.get_next_bytes_capped(MAX_DATA_LEN)?;
+
+    if bytes.len() % 4 != 0 {
+        return Err(de::Error::custom("Length of id list is not multiple of 4"));
+    }
+
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|id| u32::from_be_bytes([id[0], id[1], id[2], id[3]]))
+        .collect())
+}
+
+impl<'a> Request<'a> {
+    /// Create a request with `request_id`, e.g. one allocated by
+    /// [`crate::pending_requests::PendingRequests::allocate`].
+    pub const fn new(request_id: RequestId, inner: RequestInner<'a>) -> Self {
+        Self {
+            request_id: request_id.into_raw(),
+            inner,
+        }
+    }
+
+    /// Clone any borrowed path, handle or data, see
+    /// [`RequestInner::into_owned`].
+    pub fn into_owned(self) -> Request<'static> {
+        Request {
+            request_id: self.request_id,
+            inner: self.inner.into_owned(),
+        }
+    }
+
+    /// Decode the request from `body`, for implementing sftp server.
+    ///
+    /// * `body` - should not include the initial 4-byte which serves
+    ///   as the length of the whole packet and must contain exactly one
+    ///   request.
+    ///
+    /// Data of [`RequestInner::Write`] and [`RequestInner::Extended`] is
+    /// borrowed from `body`.
+    pub fn decode_body(body: &'a [u8]) -> ssh_format::Result<Self> {
+        if body.first() == Some(&constants::SSH_FXP_EXTENDED) {
+            let ((_packet_type, request_id, name), data) =
+                ssh_format::from_bytes::<(u8, u32, &str)>(body)?;
+
+            if !is_known_extension(name) {
+                return Ok(Request {
+                    request_id,
+                    inner: RequestInner::Extended {
+                        name: Cow::Borrowed(name),
+                        data: Cow::Borrowed(data),
+                    },
+                });
+            }
+        }
+
+        from_packet_body(body, RequestVisitor::new(true), "request")
+    }
+
+    /// See [`RequestInner::validate`].
+    pub fn validate(&self) -> Result<(), RequestError> {
+        self.inner.validate()
+    }
+
+    /// Return the length of the serialized request, including the packet
+    /// type and the request id but excluding the 4-byte length header.
+    ///
+    /// This is computed without allocating, so it can be checked against
+    /// [`crate::response::Limits::packet_len`] before serializing.
+    pub fn serialized_len(&self) -> ssh_format::Result<usize> {
+        let mut serializer = ssh_format::Serializer::new(LenCounter(0));
+        self.serialize(&mut serializer)?;
+        Ok(serializer.output.0)
+    }
+
+    /// Reserve enough space in `serializer` for serializing this request
+    /// with its 4-byte length header.
+    pub fn reserve_in<Output: SerOutput>(
+        &self,
+        serializer: &mut ssh_format::Serializer<Output>,
+    ) -> ssh_format::Result<()> {
+        serializer.reserve(self.serialized_len()? + 4);
+        Ok(())
+    }
+
+    /// Return the serialized request with the 4-byte length header, ready
+    /// to be written to the sftp channel.
+    ///
+    /// This allocates a new `Vec` for every request, use a reusable
+    /// [`ssh_format::Serializer`] (with [`Request::reserve_in`] and
+    /// [`Request::serialize_write_request`]) in performance-sensitive code.
+    pub fn encode(&self) -> ssh_format::Result<Vec<u8>> {
+        let mut serializer = ssh_format::Serializer::new(vec![0, 0, 0, 0]);
+        self.reserve_in(&mut serializer)?;
+        self.serialize(&mut serializer)?;
+
+        let header = serializer.create_header(0)?;
+        let mut packet = serializer.output;
+        packet[..4].copy_from_slice(&header);
+
+        Ok(packet)
+    }
+
+    /// The write will extend the file if writing beyond the end of the file.
+    ///
+    /// It is legal to write way beyond the end of the file, the semantics
+    /// are to write zeroes from the end of the file to the specified offset
+    /// and then the data.
+    ///
+    /// On most operating systems, such writes do not allocate disk space but
+    /// instead leave "holes" in the file.
+    ///
+    /// Responds with a [`crate::response::ResponseInner::Status`] message.
+    ///
+    /// The Write also includes any amount of custom data and its size is
+    /// included in the size of the entire packet sent.
+    ///
+    /// Return the serialized header (including the 4-byte size).
     ///
     ///  * `serializer` - must be empty
     pub fn serialize_write_request<Output: SerOutput>(
@@ -381,31 +1827,1117 @@ impl Request<'_> {
         request_id: u32,
         handle: Cow<'_, Handle>,
         offset: u64,
-        data_len: u32,
-    ) -> ssh_format::Result<[u8; 4]> {
-        (
-            constants::SSH_FXP_WRITE,
-            request_id,
+        data_len: u32,
+    ) -> ssh_format::Result<[u8; 4]> {
+        (
+            constants::SSH_FXP_WRITE,
+            request_id,
+            handle,
+            offset,
+            data_len,
+        )
+            .serialize(&mut *serializer)?;
+
+        serializer.create_header(data_len)
+    }
+
+    /// If `self` is a [`RequestInner::Write`], serialize everything except
+    /// for the data into `buffer` and return `(header, data)`, which can be
+    /// written with one `write_vectored` without copying the data.
+    ///
+    /// `header` includes the 4-byte size and is stored in `buffer`, which
+    /// is cleared first.
+    ///
+    /// Return `Ok(None)` for other requests.
+    pub fn serialize_write_vectored<'b>(
+        &'b self,
+        buffer: &'b mut Vec<u8>,
+    ) -> ssh_format::Result<Option<(&'b [u8], &'b [u8])>> {
+        let (handle, offset, data) = match &self.inner {
+            RequestInner::Write {
+                handle,
+                offset,
+                data,
+            } => (handle, *offset, &**data),
+            _ => return Ok(None),
+        };
+
+        let data_len: u32 = data
+            .len()
+            .try_into()
+            .map_err(|_| ssh_format::Error::TooLong)?;
+
+        buffer.clear();
+        buffer.extend_from_slice(&[0, 0, 0, 0]);
+
+        let mut serializer = ssh_format::Serializer::new(&mut *buffer);
+        let header = Self::serialize_write_request(
+            &mut serializer,
+            self.request_id,
+            Cow::Borrowed(&**handle),
+            offset,
+            data_len,
+        )?;
+
+        buffer[..4].copy_from_slice(&header);
+
+        Ok(Some((buffer, data)))
+    }
+}
+
+/// Id of a request, which is echoed back in the response.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct RequestId(u32);
+
+impl RequestId {
+    pub const fn from_raw(request_id: u32) -> Self {
+        Self(request_id)
+    }
+
+    pub const fn into_raw(self) -> u32 {
+        self.0
+    }
+}
+
+impl From<RequestId> for u32 {
+    fn from(request_id: RequestId) -> Self {
+        request_id.into_raw()
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// Create [`Request`]s with unique `request_id`s.
+///
+/// Ids are allocated sequentially and wrap around after [`u32::MAX`], so
+/// an id is only reused after `2^32` requests have been created by the
+/// same builder.
+///
+/// Use [`crate::pending_requests::PendingRequests::allocate`] on
+/// long-lived connections to also avoid reusing ids that are still in
+/// flight.
+#[derive(Debug, Clone, Default)]
+pub struct RequestBuilder {
+    next_id: u32,
+}
+
+impl RequestBuilder {
+    pub const fn new() -> Self {
+        Self::starting_at(0)
+    }
+
+    /// * `request_id` - id of the first request to be created.
+    pub const fn starting_at(request_id: u32) -> Self {
+        Self {
+            next_id: request_id,
+        }
+    }
+
+    /// Allocate a new request id.
+    pub fn next_request_id(&mut self) -> u32 {
+        let request_id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        request_id
+    }
+
+    /// Wrap `inner` with a new request id.
+    pub fn build<'a>(&mut self, inner: RequestInner<'a>) -> Request<'a> {
+        Request {
+            request_id: self.next_request_id(),
+            inner,
+        }
+    }
+
+    pub fn open<'a>(&mut self, request: OpenFileRequest<'a>) -> Request<'a> {
+        self.build(RequestInner::Open(request))
+    }
+
+    pub fn close<'a>(&mut self, handle: Cow<'a, Handle>) -> Request<'a> {
+        self.build(RequestInner::Close(handle))
+    }
+
+    pub fn read<'a>(&mut self, handle: Cow<'a, Handle>, offset: u64, len: u32) -> Request<'a> {
+        self.build(RequestInner::read_at(handle, offset, len))
+    }
+
+    pub fn write<'a>(
+        &mut self,
+        handle: Cow<'a, Handle>,
+        offset: u64,
+        data: Cow<'a, [u8]>,
+    ) -> Request<'a> {
+        self.build(RequestInner::Write {
             handle,
             offset,
-            data_len,
-        )
-            .serialize(&mut *serializer)?;
+            data,
+        })
+    }
 
-        serializer.create_header(data_len)
+    pub fn remove<'a>(&mut self, path: Cow<'a, Path>) -> Request<'a> {
+        self.build(RequestInner::Remove(path))
+    }
+
+    pub fn rename<'a>(&mut self, oldpath: Cow<'a, Path>, newpath: Cow<'a, Path>) -> Request<'a> {
+        self.build(RequestInner::Rename { oldpath, newpath })
+    }
+
+    pub fn mkdir<'a>(&mut self, path: Cow<'a, Path>) -> Request<'a> {
+        self.build(RequestInner::mkdir(path))
+    }
+
+    pub fn rmdir<'a>(&mut self, path: Cow<'a, Path>) -> Request<'a> {
+        self.build(RequestInner::Rmdir(path))
+    }
+
+    pub fn opendir<'a>(&mut self, path: Cow<'a, Path>) -> Request<'a> {
+        self.build(RequestInner::Opendir(path))
+    }
+
+    pub fn readdir<'a>(&mut self, handle: Cow<'a, Handle>) -> Request<'a> {
+        self.build(RequestInner::Readdir(handle))
+    }
+
+    pub fn stat<'a>(&mut self, path: Cow<'a, Path>) -> Request<'a> {
+        self.build(RequestInner::Stat(path))
+    }
+
+    pub fn lstat<'a>(&mut self, path: Cow<'a, Path>) -> Request<'a> {
+        self.build(RequestInner::Lstat(path))
+    }
+
+    pub fn fstat<'a>(&mut self, handle: Cow<'a, Handle>) -> Request<'a> {
+        self.build(RequestInner::Fstat(handle))
+    }
+
+    pub fn setstat<'a>(&mut self, path: Cow<'a, Path>, attrs: FileAttrs) -> Request<'a> {
+        self.build(RequestInner::Setstat { path, attrs })
+    }
+
+    pub fn fsetstat<'a>(&mut self, handle: Cow<'a, Handle>, attrs: FileAttrs) -> Request<'a> {
+        self.build(RequestInner::Fsetstat { handle, attrs })
+    }
+
+    pub fn readlink<'a>(&mut self, path: Cow<'a, Path>) -> Request<'a> {
+        self.build(RequestInner::Readlink(path))
     }
+
+    pub fn symlink<'a>(
+        &mut self,
+        linkpath: Cow<'a, Path>,
+        targetpath: Cow<'a, Path>,
+    ) -> Request<'a> {
+        self.build(RequestInner::Symlink {
+            linkpath,
+            targetpath,
+        })
+    }
+
+    pub fn realpath<'a>(&mut self, path: Cow<'a, Path>) -> Request<'a> {
+        self.build(RequestInner::Realpath(path))
+    }
+}
+
+/// List of uids/gids, serialized as a string containing the ids.
+struct IdList<'a>(&'a [u32]);
+
+impl Serialize for IdList<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let len: u32 = self
+            .0
+            .len()
+            .checked_mul(4)
+            .and_then(|len| len.try_into().ok())
+            .ok_or_else(|| S::Error::custom("Too many ids"))?;
+
+        // dummy size since ssh_format doesn't care
+        let mut tuple_serializer = serializer.serialize_tuple(1)?;
+
+        tuple_serializer.serialize_element(&len)?;
+        for id in self.0 {
+            tuple_serializer.serialize_element(id)?;
+        }
+
+        tuple_serializer.end()
+    }
+}
+
+/// [`SerOutput`] that only counts the bytes written to it.
+struct LenCounter(usize);
+
+impl SerOutput for LenCounter {
+    fn extend_from_slice(&mut self, other: &[u8]) {
+        self.0 += other.len();
+    }
+
+    fn push(&mut self, _byte: u8) {
+        self.0 += 1;
+    }
+
+    fn reserve(&mut self, _additional: usize) {}
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Serialize)]
 pub struct OpenFileRequest<'a> {
     pub(crate) filename: Cow<'a, Path>,
     pub(crate) flags: u32,
     pub(crate) attrs: FileAttrs,
 }
 
+impl fmt::Debug for OpenFileRequest<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OpenFileRequest")
+            .field("filename", &DebugPath(&self.filename))
+            .field("flags", &self.flags)
+            .field("attrs", &self.attrs)
+            .finish()
+    }
+}
+
 impl<'a> OpenFileRequest<'a> {
     /// Open file in read only mode
     pub const fn open(filename: Cow<'a, Path>) -> Self {
         OpenOptions::new().read(true).open(filename)
     }
+
+    /// Clone `filename` if it is borrowed.
+    pub fn into_owned(self) -> OpenFileRequest<'static> {
+        OpenFileRequest {
+            filename: into_owned_cow(self.filename),
+            flags: self.flags,
+            attrs: self.attrs,
+        }
+    }
+
+    pub fn filename(&self) -> &Path {
+        &self.filename
+    }
+
+    /// Return the `SSH_FXF_*` flags, see [`OpenOptions::flags`].
+    pub const fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// Replace attributes used when creating the file.
+    pub fn set_attrs(&mut self, attrs: FileAttrs) {
+        self.attrs = attrs;
+    }
+
+    /// Return attributes used when creating the file.
+    pub fn attrs_mut(&mut self) -> &mut FileAttrs {
+        &mut self.attrs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::HandleOwned;
+
+    use vec_strings::SmallArrayBox;
+
+    fn serialize_request(inner: RequestInner<'_>) -> Vec<u8> {
+        ssh_format::to_bytes(&Request {
+            request_id: 0x1234,
+            inner,
+        })
+        .unwrap()
+    }
+
+    fn extended_request(ext_name: &str, operand: &[u8]) -> Vec<u8> {
+        let mut expected = Vec::new();
+
+        expected.push(constants::SSH_FXP_EXTENDED);
+        expected.extend_from_slice(&0x1234_u32.to_be_bytes());
+        expected.extend_from_slice(&(ext_name.len() as u32).to_be_bytes());
+        expected.extend_from_slice(ext_name.as_bytes());
+        expected.extend_from_slice(&(operand.len() as u32).to_be_bytes());
+        expected.extend_from_slice(operand);
+
+        let mut packet = (expected.len() as u32).to_be_bytes().to_vec();
+        packet.extend_from_slice(&expected);
+        packet
+    }
+
+    #[test]
+    fn test_serialized_len() {
+        let handle = HandleOwned(SmallArrayBox::new(*b"1234"));
+        let request = Request {
+            request_id: 0x1234,
+            inner: RequestInner::rename_with_flags(
+                Cow::Borrowed(Path::new("/a")),
+                Cow::Borrowed(Path::new("/tmp/b")),
+                RenameFlags::OVERWRITE,
+            ),
+        };
+        let fstatvfs = Request {
+            request_id: 0x1234,
+            inner: RequestInner::Fstatvfs(Cow::Borrowed(&handle)),
+        };
+        let write = Request {
+            request_id: 0x1234,
+            inner: RequestInner::Write {
+                handle: Cow::Borrowed(&handle),
+                offset: 0,
+                data: Cow::Owned(vec![0; 1000]),
+            },
+        };
+
+        // packet type (1) + request id (4) + handle (4 + 4)
+        // + offset (8) + data (4 + 1000)
+        assert_eq!(write.serialized_len().unwrap(), 1 + 4 + 8 + 8 + 1004);
+
+        for request in [request, fstatvfs, write] {
+            let bytes = ssh_format::to_bytes(&request).unwrap();
+            assert_eq!(request.encode().unwrap(), bytes);
+
+            assert_eq!(request.serialized_len().unwrap(), bytes.len() - 4);
+
+            let mut serializer = ssh_format::Serializer::new(Vec::new());
+            request.reserve_in(&mut serializer).unwrap();
+            assert!(serializer.output.capacity() >= bytes.len());
+        }
+    }
+
+    #[test]
+    fn test_serialize_write_vectored() {
+        let handle = HandleOwned(SmallArrayBox::new(*b"1234"));
+        let request = Request {
+            request_id: 0x1234,
+            inner: RequestInner::Write {
+                handle: Cow::Borrowed(&handle),
+                offset: 100,
+                data: Cow::Owned(vec![1, 2, 3]),
+            },
+        };
+
+        let mut buffer = vec![0xff; 3];
+        let (header, data) = request
+            .serialize_write_vectored(&mut buffer)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(data, &[1, 2, 3]);
+        assert_eq!([header, data].concat(), request.encode().unwrap());
+
+        let request = Request {
+            request_id: 0x1234,
+            inner: RequestInner::Close(Cow::Borrowed(&handle)),
+        };
+        assert!(request
+            .serialize_write_vectored(&mut buffer)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_request_builder() {
+        let handle = HandleOwned(SmallArrayBox::new(*b"1234"));
+        let mut builder = RequestBuilder::new();
+
+        let request = builder.opendir(Cow::Borrowed(Path::new("/tmp")));
+        assert_eq!(request.request_id, 0);
+        assert!(matches!(request.inner, RequestInner::Opendir(path) if path == Path::new("/tmp")));
+
+        let request = builder.read(Cow::Borrowed(&handle), 10, 20);
+        assert_eq!(request.request_id, 1);
+        assert!(matches!(
+            request.inner,
+            RequestInner::Read {
+                offset: 10,
+                len: 20,
+                ..
+            }
+        ));
+
+        assert_eq!(builder.close(Cow::Borrowed(&handle)).request_id, 2);
+
+        let mut builder = RequestBuilder::starting_at(u32::MAX);
+        assert_eq!(builder.next_request_id(), u32::MAX);
+        assert_eq!(builder.build(RequestInner::Limits).request_id, 0);
+    }
+
+    #[test]
+    fn test_validate() {
+        let valid = Path::new("/tmp/a");
+        let invalid = Path::new("/tmp/a\0b");
+
+        assert_eq!(RequestInner::mkdir(Cow::Borrowed(valid)).validate(), Ok(()));
+        assert_eq!(RequestInner::Limits.validate(), Ok(()));
+
+        assert_eq!(
+            RequestInner::Open(OpenFileRequest::open(Cow::Borrowed(invalid))).validate(),
+            Err(RequestError::PathContainsNul {
+                len: 8,
+                nul_offset: 6
+            })
+        );
+
+        let request = Request {
+            request_id: 0,
+            inner: RequestInner::posix_rename(Cow::Borrowed(valid), Cow::Borrowed(invalid)),
+        };
+        assert_eq!(
+            request.validate(),
+            Err(RequestError::PathContainsNul {
+                len: 8,
+                nul_offset: 6
+            })
+        );
+
+        // The path itself is not leaked
+        assert_eq!(
+            request.validate().unwrap_err().to_string(),
+            "Path of 8 bytes contains interior NUL byte at offset 6."
+        );
+    }
+
+    #[test]
+    fn test_packet_type() {
+        let handle = HandleOwned(SmallArrayBox::new(*b"1234"));
+        let path = Path::new("/tmp");
+
+        let requests = [
+            RequestInner::Open(OpenFileRequest::open(Cow::Borrowed(path))),
+            RequestInner::read_at(Cow::Borrowed(&handle), 0, 10),
+            RequestInner::rename_with_flags(
+                Cow::Borrowed(path),
+                Cow::Borrowed(path),
+                RenameFlags::OVERWRITE,
+            ),
+            RequestInner::mkdir(Cow::Borrowed(path)),
+            RequestInner::Limits,
+            RequestInner::HomeDirectory(Cow::Borrowed("")),
+            RequestInner::posix_rename(Cow::Borrowed(path), Cow::Borrowed(path)),
+        ];
+
+        for inner in requests {
+            let packet_type = inner.packet_type();
+            let extension_name = inner.extension_name();
+
+            let bytes = serialize_request(inner);
+            assert_eq!(bytes[4], packet_type);
+
+            if packet_type == constants::SSH_FXP_EXTENDED {
+                let name = extension_name.unwrap();
+                assert_eq!(&bytes[13..13 + name.len()], name.as_bytes());
+            } else {
+                assert_eq!(extension_name, None);
+            }
+        }
+    }
+
+    #[test]
+    fn test_required_extension() {
+        let path = Path::new("/tmp");
+
+        let request = RequestInner::Stat(Cow::Borrowed(path));
+        assert!(!request.is_extension());
+        assert_eq!(request.required_extension(), None);
+
+        let request = RequestInner::posix_rename(Cow::Borrowed(path), Cow::Borrowed(path));
+        assert!(request.is_extension());
+        assert_eq!(request.required_extension(), Some(Extensions::POSIX_RENAME));
+        assert_eq!(
+            request.required_extension().and_then(Extensions::name),
+            request.extension_name()
+        );
+
+        let request = RequestInner::HomeDirectory(Cow::Borrowed(""));
+        assert_eq!(
+            request.required_extension().and_then(Extensions::name),
+            request.extension_name()
+        );
+    }
+
+    #[test]
+    fn test_deserialize_request() {
+        let handle = HandleOwned::new(*b"1234");
+        let path = Path::new("/tmp/a");
+        let mut attrs = FileAttrs::new();
+        attrs.set_size(100);
+
+        let requests = [
+            RequestInner::Open(OpenOptions::new().write(true).truncate(true).create(
+                Cow::Borrowed(path),
+                crate::open_options::CreateFlags::Excl,
+                attrs.clone(),
+            )),
+            RequestInner::Close(Cow::Borrowed(&handle)),
+            RequestInner::read_at(Cow::Borrowed(&handle), 10, 20),
+            RequestInner::Write {
+                handle: Cow::Borrowed(&handle),
+                offset: 10,
+                data: Cow::Borrowed(b"data"),
+            },
+            RequestInner::Rename {
+                oldpath: Cow::Borrowed(path),
+                newpath: Cow::Borrowed(Path::new("/tmp/b")),
+            },
+            RequestInner::rename_with_flags(
+                Cow::Borrowed(path),
+                Cow::Borrowed(Path::new("/tmp/b")),
+                RenameFlags::OVERWRITE,
+            ),
+            RequestInner::mkdir_with_mode(Cow::Borrowed(path), Permissions::from_mode(0o755)),
+            RequestInner::Fsetstat {
+                handle: Cow::Borrowed(&handle),
+                attrs: attrs.clone(),
+            },
+            RequestInner::Symlink {
+                linkpath: Cow::Borrowed(path),
+                targetpath: Cow::Borrowed(Path::new("/tmp/b")),
+            },
+            RequestInner::Limits,
+            RequestInner::HomeDirectory(Cow::Borrowed("user")),
+            RequestInner::check_file_handle(
+                Cow::Borrowed(&handle),
+                &HashAlgorithms::new().with(HashAlgorithm::Sha256),
+                0,
+                0,
+                4096,
+            ),
+            RequestInner::UsersGroupsById {
+                uids: Cow::Borrowed(&[0, 1000]),
+                gids: Cow::Borrowed(&[]),
+            },
+            RequestInner::Lsetstat(Cow::Borrowed(path), attrs),
+            RequestInner::posix_rename(Cow::Borrowed(path), Cow::Borrowed(Path::new("/tmp/b"))),
+            RequestInner::Cp {
+                read_from_handle: Cow::Borrowed(&handle),
+                read_from_offset: 1,
+                read_data_length: CopyLength::ToEof,
+                write_to_handle: Cow::Borrowed(&handle),
+                write_to_offset: 2,
+            },
+        ];
+
+        for inner in requests {
+            let bytes = serialize_request(inner);
+            let request = Request::decode_body(&bytes[4..]).unwrap();
+
+            assert_eq!(request.request_id, 0x1234);
+            assert_eq!(ssh_format::to_bytes(&request).unwrap(), bytes);
+        }
+
+        let mut bytes = serialize_request(RequestInner::Limits);
+        bytes.push(0);
+        Request::decode_body(&bytes[4..]).unwrap_err();
+
+        let bytes = extended_request("unknown@example.com", b"");
+        Request::decode_body(&bytes[4..bytes.len() - 6]).unwrap_err();
+    }
+
+    #[test]
+    fn test_extended() {
+        let request = RequestInner::extended(
+            Cow::Borrowed("make-temp-folder@example.com"),
+            &(Path::new("/tmp"),),
+        )
+        .unwrap();
+        assert_eq!(request.packet_type(), constants::SSH_FXP_EXTENDED);
+        assert!(request.is_extension());
+        assert_eq!(request.extension_name(), None);
+        assert_eq!(request.required_extension(), None);
+
+        let bytes = serialize_request(request);
+        assert_eq!(
+            bytes,
+            extended_request("make-temp-folder@example.com", b"/tmp")
+        );
+
+        let request = Request::decode_body(&bytes[4..]).unwrap();
+        assert!(matches!(
+            &request.inner,
+            RequestInner::Extended { name, data }
+                if name == "make-temp-folder@example.com" && data[..] == bytes[bytes.len() - 8..]
+        ));
+        assert_eq!(ssh_format::to_bytes(&request).unwrap(), bytes);
+
+        let request = RequestInner::Extended {
+            name: Cow::Borrowed("get-temp-folder@example.com"),
+            data: Cow::Borrowed(b""),
+        };
+        let bytes = serialize_request(request.into_owned());
+        let request = Request::decode_body(&bytes[4..]).unwrap();
+        assert!(matches!(
+            request.inner,
+            RequestInner::Extended { data, .. } if data.is_empty()
+        ));
+    }
+
+    #[test]
+    fn test_deserialize_borrowed_data() {
+        let handle = HandleOwned::new(*b"1234");
+        let data = vec![1_u8; MAX_DATA_LEN + 1];
+
+        let bytes = serialize_request(RequestInner::Write {
+            handle: Cow::Borrowed(&handle),
+            offset: 10,
+            data: Cow::Borrowed(&data[..MAX_DATA_LEN]),
+        });
+        let request = Request::decode_body(&bytes[4..]).unwrap();
+        match &request.inner {
+            RequestInner::Write {
+                data: Cow::Borrowed(data),
+                ..
+            } => assert_eq!(data.as_ptr(), bytes[bytes.len() - MAX_DATA_LEN..].as_ptr()),
+            request => panic!("Unexpected request {:#?}", request),
+        }
+
+        let bytes = serialize_request(RequestInner::Write {
+            handle: Cow::Borrowed(&handle),
+            offset: 10,
+            data: Cow::Borrowed(&data),
+        });
+        Request::decode_body(&bytes[4..]).unwrap_err();
+
+        let bytes = extended_request("unknown@example.com", b"data");
+        let request = Request::decode_body(&bytes[4..]).unwrap();
+        match &request.inner {
+            RequestInner::Extended {
+                name: Cow::Borrowed(name),
+                data: Cow::Borrowed(data),
+            } => {
+                assert_eq!(*name, "unknown@example.com");
+                assert_eq!(data.as_ptr(), bytes[bytes.len() - 8..].as_ptr());
+            }
+            request => panic!("Unexpected request {:#?}", request),
+        }
+
+        // Deserialize cannot tell where the payload ends
+        let err = ssh_format::from_bytes::<Request>(&bytes[4..]).unwrap_err();
+        assert!(err.to_string().contains("Request::decode_body"), "{}", err);
+    }
+
+    #[test]
+    fn test_deserialize_rename_stream() {
+        let oldpath = Path::new("/tmp/a");
+        let newpath = Path::new("/tmp/b");
+
+        let rename = serialize_request(RequestInner::Rename {
+            oldpath: Cow::Borrowed(oldpath),
+            newpath: Cow::Borrowed(newpath),
+        });
+        let stat = serialize_request(RequestInner::Stat(Cow::Borrowed(oldpath)));
+
+        let (request, rest) = ssh_format::from_bytes::<Request>(&rename[4..]).unwrap();
+        assert!(rest.is_empty());
+        assert!(matches!(request.inner, RequestInner::Rename { .. }));
+
+        // The stat following the rename must not be read as its flags
+        let mut stream = rename[4..].to_vec();
+        stream.extend_from_slice(&stat[4..]);
+
+        let de = &mut ssh_format::Deserializer::from_bytes(&stream);
+        assert!(matches!(
+            Request::deserialize(&mut *de).unwrap().inner,
+            RequestInner::Rename { .. }
+        ));
+        assert!(matches!(
+            Request::deserialize(&mut *de).unwrap().inner,
+            RequestInner::Stat(path) if path == oldpath
+        ));
+        assert!(!de.has_remaining_data());
+
+        // Only decode_body reads the flags
+        let bytes = serialize_request(RequestInner::rename_with_flags(
+            Cow::Borrowed(oldpath),
+            Cow::Borrowed(newpath),
+            RenameFlags::OVERWRITE,
+        ));
+        assert!(matches!(
+            Request::decode_body(&bytes[4..]).unwrap().inner,
+            RequestInner::RenameWithFlags { flags, .. } if flags == RenameFlags::OVERWRITE
+        ));
+    }
+
+    #[test]
+    fn test_deserialize_hello() {
+        let hello = Hello::new(3)
+            .with_extension("a@example.com", "1")
+            .with_extension("b@example.com", "2");
+        assert_eq!(hello.extensions.len(), 2);
+
+        let bytes = ssh_format::to_bytes(&hello).unwrap();
+
+        let decoded = Hello::decode_body(&bytes[4..]).unwrap();
+        assert_eq!(decoded.version, 3);
+        assert_eq!(decoded.extensions, hello.extensions);
+
+        let decoded = Hello::decode_body(&bytes[4..9]).unwrap();
+        assert!(decoded.extensions.is_empty());
+
+        // Extension without data
+        Hello::decode_body(&bytes[4..30]).unwrap_err();
+
+        let bytes = serialize_request(RequestInner::Limits);
+        Hello::decode_body(&bytes[4..]).unwrap_err();
+    }
+
+    #[test]
+    fn test_into_owned() {
+        fn assert_static<T: 'static>(_: &T) {}
+
+        let path = String::from("/tmp/a");
+        let data = vec![1, 2, 3];
+        let handle = HandleOwned(SmallArrayBox::new(*b"1234"));
+
+        let requests = [
+            RequestInner::Open(OpenFileRequest::open(Cow::Borrowed(Path::new(&path)))),
+            RequestInner::Rename {
+                oldpath: Cow::Borrowed(Path::new(&path)),
+                newpath: Cow::Borrowed(Path::new("/tmp/b")),
+            },
+            RequestInner::Write {
+                handle: Cow::Borrowed(&handle),
+                offset: 0,
+                data: Cow::Borrowed(&data),
+            },
+        ];
+
+        for inner in requests {
+            let request = Request {
+                request_id: 0x1234,
+                inner,
+            };
+            let bytes = ssh_format::to_bytes(&request).unwrap();
+
+            let request = request.into_owned();
+            assert_static(&request);
+            assert_eq!(ssh_format::to_bytes(&request).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn test_hash_algorithms() {
+        use HashAlgorithm::*;
+
+        let algorithms = HashAlgorithms::new()
+            .with(Sha256)
+            .with(Md5)
+            .with(Sha256)
+            .with(Crc32);
+
+        assert_eq!(algorithms.as_slice(), &[Sha256, Md5, Crc32]);
+        assert_eq!(algorithms.to_string(), "sha256,md5,crc32");
+        assert_eq!(
+            &ssh_format::to_bytes(&algorithms).unwrap()[4..],
+            b"\0\0\0\x10sha256,md5,crc32"
+        );
+
+        assert_eq!(HashAlgorithm::from_name("sha512"), Some(Sha512));
+        assert_eq!(HashAlgorithm::from_name("sha3"), None);
+    }
+
+    #[test]
+    fn test_split_cp() {
+        let src = HandleOwned(SmallArrayBox::new(*b"src"));
+        let dst = HandleOwned(SmallArrayBox::new(*b"dst"));
+
+        let max_len = NonZeroU64::new(100).unwrap();
+        let offsets = |chunks: CpChunks<'_>| -> Vec<_> {
+            chunks
+                .map(|request| match request {
+                    RequestInner::Cp {
+                        read_from_offset,
+                        read_data_length,
+                        write_to_offset,
+                        ..
+                    } => (
+                        read_from_offset,
+                        read_data_length.into_raw(),
+                        write_to_offset,
+                    ),
+                    request => panic!("Unexpected request {:#?}", request),
+                })
+                .collect()
+        };
+
+        let len = CopyLength::from_raw(250);
+        let chunks = RequestInner::split_cp(&src, 10, len, &dst, 1000, max_len);
+        assert_eq!(
+            offsets(chunks),
+            [(10, 100, 1000), (110, 100, 1100), (210, 50, 1200)]
+        );
+
+        // Offsets of the second chunk would overflow
+        let chunks = RequestInner::split_cp(&src, u64::MAX - 150, len, &dst, 1000, max_len);
+        assert_eq!(
+            offsets(chunks),
+            [(u64::MAX - 150, 100, 1000), (u64::MAX - 50, 100, 1100)]
+        );
+
+        let len = CopyLength::ToEof;
+        let mut chunks = RequestInner::split_cp(&src, 10, len, &dst, 1000, max_len);
+        assert!(matches!(
+            chunks.next(),
+            Some(RequestInner::Cp {
+                read_from_offset: 10,
+                read_data_length: CopyLength::ToEof,
+                write_to_offset: 1000,
+                ..
+            })
+        ));
+        assert!(chunks.next().is_none());
+    }
+
+    #[test]
+    fn test_rename_flags() {
+        let flags = RenameFlags::OVERWRITE | RenameFlags::ATOMIC;
+
+        assert_eq!(
+            ssh_format::to_bytes(&flags).unwrap(),
+            [0, 0, 0, 4, 0, 0, 0, 3]
+        );
+        assert_eq!(
+            ssh_format::from_bytes::<RenameFlags>(&[0, 0, 0, 3])
+                .unwrap()
+                .0,
+            flags
+        );
+        assert_eq!(flags.to_string(), "OVERWRITE | ATOMIC");
+    }
+
+    #[test]
+    fn test_debug() {
+        let handle = HandleOwned(SmallArrayBox::new(*b"secret-handle"));
+        let request = Request {
+            request_id: 0,
+            inner: RequestInner::Rename {
+                oldpath: Cow::Borrowed(Path::new("/secret/old")),
+                newpath: Cow::Borrowed(Path::new("/secret/new")),
+            },
+        };
+        let extended =
+            RequestInner::extended(Cow::Borrowed("a@example.com"), &(Path::new("/secret/abc"),))
+                .unwrap();
+        let write = RequestInner::Write {
+            handle: Cow::Borrowed(&*handle),
+            offset: 0,
+            data: Cow::Borrowed(b"secret file data"),
+        };
+        let home_directory = RequestInner::HomeDirectory(Cow::Borrowed("secret-user-name"));
+        let debug = format!(
+            "{:?} {:?} {:?} {:?} {:?}",
+            request,
+            RequestInner::Fsync(Cow::Borrowed(&*handle)),
+            extended,
+            write,
+            home_directory,
+        );
+
+        if cfg!(feature = "redact-debug") {
+            assert!(!debug.contains("secret"), "{}", debug);
+            assert!(debug.contains("<redacted 11 bytes>"), "{}", debug);
+            assert!(debug.contains("<redacted 13 bytes>"), "{}", debug);
+            assert!(debug.contains("<redacted 15 bytes>"), "{}", debug);
+            assert!(debug.contains("<redacted 16 bytes>"), "{}", debug);
+        } else {
+            assert!(debug.contains("\"secret-user-name\""), "{}", debug);
+            assert!(debug.contains("/secret/old"), "{}", debug);
+            assert!(debug.contains("/secret/new"), "{}", debug);
+        }
+    }
+
+    #[test]
+    fn test_read_at_and_read_all() {
+        let handle = HandleOwned::new(*b"1234");
+        let read_len = |request: &RequestInner<'_>| match request {
+            RequestInner::Read { offset, len, .. } => (*offset, *len),
+            request => panic!("Unexpected request {:#?}", request),
+        };
+
+        let request = RequestInner::read_at(Cow::Borrowed(&handle), 100, 20);
+        assert_eq!(read_len(&request), (100, 20));
+
+        // size < max_read_len
+        let request = RequestInner::read_all(Cow::Borrowed(&handle), 10, 4096);
+        assert_eq!(read_len(&request), (0, 10));
+
+        // size > max_read_len, capped
+        let request = RequestInner::read_all(Cow::Borrowed(&handle), u64::MAX, 4096);
+        assert_eq!(read_len(&request), (0, 4096));
+
+        // size == 0
+        let request = RequestInner::read_all(Cow::Borrowed(&handle), 0, 4096);
+        assert_eq!(read_len(&request), (0, 0));
+
+        let mut expected = vec![constants::SSH_FXP_READ];
+        expected.extend_from_slice(&0x1234_u32.to_be_bytes());
+        expected.extend_from_slice(b"\0\0\0\x041234");
+        expected.extend_from_slice(&0_u64.to_be_bytes());
+        expected.extend_from_slice(&4096_u32.to_be_bytes());
+
+        let request = RequestInner::read_all(Cow::Borrowed(&handle), 5000, 4096);
+        assert_eq!(serialize_request(request)[4..], expected);
+    }
+
+    #[test]
+    fn test_open_file_request_attrs() {
+        let path = Path::new("/tmp/a");
+
+        let mut request = OpenFileRequest::open(Cow::Borrowed(path));
+        let mut attrs = FileAttrs::new();
+        attrs.set_size(100);
+        request.set_attrs(attrs);
+        request
+            .attrs_mut()
+            .set_permissions(Permissions::from_mode(0o644));
+
+        let mut expected = vec![constants::SSH_FXP_OPEN];
+        expected.extend_from_slice(&0x1234_u32.to_be_bytes());
+        expected.extend_from_slice(b"\0\0\0\x06/tmp/a");
+        expected.extend_from_slice(&constants::SSH_FXF_READ.to_be_bytes());
+        expected.extend_from_slice(
+            &(constants::SSH_FILEXFER_ATTR_SIZE | constants::SSH_FILEXFER_ATTR_PERMISSIONS)
+                .to_be_bytes(),
+        );
+        expected.extend_from_slice(&100_u64.to_be_bytes());
+        expected.extend_from_slice(&0o644_u32.to_be_bytes());
+
+        assert_eq!(
+            serialize_request(RequestInner::Open(request))[4..],
+            expected
+        );
+    }
+
+    #[test]
+    fn test_mkdir() {
+        let path = Path::new("/tmp/dir");
+
+        let mut expected = vec![constants::SSH_FXP_MKDIR];
+        expected.extend_from_slice(&0x1234_u32.to_be_bytes());
+        expected.extend_from_slice(b"\0\0\0\x08/tmp/dir");
+
+        let mut packet = ((expected.len() + 4) as u32).to_be_bytes().to_vec();
+        packet.extend_from_slice(&expected);
+        packet.extend_from_slice(&0_u32.to_be_bytes());
+
+        assert_eq!(
+            serialize_request(RequestInner::mkdir(Cow::Borrowed(path))),
+            packet
+        );
+
+        let mut packet = ((expected.len() + 8) as u32).to_be_bytes().to_vec();
+        packet.extend_from_slice(&expected);
+        packet.extend_from_slice(&constants::SSH_FILEXFER_ATTR_PERMISSIONS.to_be_bytes());
+        packet.extend_from_slice(&0o755_u32.to_be_bytes());
+
+        let permissions = Permissions::from_bits(0o755).unwrap();
+        assert_eq!(
+            serialize_request(RequestInner::mkdir_with_mode(
+                Cow::Borrowed(path),
+                permissions
+            )),
+            packet
+        );
+    }
+
+    #[test]
+    fn test_posix_rename() {
+        let request = RequestInner::posix_rename(
+            Cow::Borrowed(Path::new("/a")),
+            Cow::Borrowed(Path::new("/b")),
+        );
+
+        let mut expected = vec![constants::SSH_FXP_EXTENDED];
+        expected.extend_from_slice(&0x1234_u32.to_be_bytes());
+        expected.extend_from_slice(b"\0\0\0\x18posix-rename@openssh.com");
+        expected.extend_from_slice(b"\0\0\0\x02/a\0\0\0\x02/b");
+
+        let mut packet = (expected.len() as u32).to_be_bytes().to_vec();
+        packet.extend_from_slice(&expected);
+
+        assert_eq!(serialize_request(request), packet);
+    }
+
+    #[test]
+    fn test_check_file_handle() {
+        let handle = HandleOwned(SmallArrayBox::new(*b"1234"));
+        let algorithms = HashAlgorithms::new()
+            .with(HashAlgorithm::Sha256)
+            .with(HashAlgorithm::Md5);
+
+        let request =
+            RequestInner::check_file_handle(Cow::Borrowed(&handle), &algorithms, 1, 1024, 512);
+
+        let mut expected = vec![constants::SSH_FXP_EXTENDED];
+        expected.extend_from_slice(&0x1234_u32.to_be_bytes());
+        expected.extend_from_slice(b"\0\0\0\x11check-file-handle");
+        expected.extend_from_slice(b"\0\0\0\x041234");
+        expected.extend_from_slice(b"\0\0\0\x0asha256,md5");
+        expected.extend_from_slice(&1_u64.to_be_bytes());
+        expected.extend_from_slice(&1024_u64.to_be_bytes());
+        expected.extend_from_slice(&512_u32.to_be_bytes());
+
+        let mut packet = (expected.len() as u32).to_be_bytes().to_vec();
+        packet.extend_from_slice(&expected);
+
+        assert_eq!(serialize_request(request), packet);
+    }
+
+    #[test]
+    fn test_home_directory() {
+        assert_eq!(
+            serialize_request(RequestInner::HomeDirectory(Cow::Borrowed("user"))),
+            extended_request(constants::EXT_NAME_HOME_DIRECTORY.0, b"user"),
+        );
+    }
+
+    #[test]
+    fn test_users_groups_by_id() {
+        let request = RequestInner::UsersGroupsById {
+            uids: Cow::Borrowed(&[0, 1000]),
+            gids: Cow::Borrowed(&[]),
+        };
+
+        let mut expected = vec![constants::SSH_FXP_EXTENDED];
+        expected.extend_from_slice(&0x1234_u32.to_be_bytes());
+        expected.extend_from_slice(b"\0\0\0\x1eusers-groups-by-id@openssh.com");
+        expected.extend_from_slice(&8_u32.to_be_bytes());
+        expected.extend_from_slice(&0_u32.to_be_bytes());
+        expected.extend_from_slice(&1000_u32.to_be_bytes());
+        expected.extend_from_slice(&0_u32.to_be_bytes());
+
+        let mut packet = (expected.len() as u32).to_be_bytes().to_vec();
+        packet.extend_from_slice(&expected);
+
+        assert_eq!(serialize_request(request), packet);
+    }
+
+    #[test]
+    fn test_deserialize_users_groups_by_id_too_long() {
+        let uids = vec![0_u32; MAX_DATA_LEN / 4 + 1];
+
+        let bytes = serialize_request(RequestInner::UsersGroupsById {
+            uids: Cow::Borrowed(&uids[..MAX_DATA_LEN / 4]),
+            gids: Cow::Borrowed(&[]),
+        });
+        Request::decode_body(&bytes[4..]).unwrap();
+
+        let bytes = serialize_request(RequestInner::UsersGroupsById {
+            uids: Cow::Borrowed(&uids),
+            gids: Cow::Borrowed(&[]),
+        });
+        Request::decode_body(&bytes[4..]).unwrap_err();
+    }
+
+    #[test]
+    fn test_statvfs_and_fstatvfs() {
+        let handle = HandleOwned(SmallArrayBox::new(b"\x00\x01handle".iter().copied()));
+
+        assert_eq!(
+            serialize_request(RequestInner::Statvfs(Cow::Borrowed(Path::new("/tmp")))),
+            extended_request(constants::EXT_NAME_STATVFS.0, b"/tmp"),
+        );
+        assert_eq!(
+            serialize_request(RequestInner::Fstatvfs(Cow::Borrowed(&*handle))),
+            extended_request(constants::EXT_NAME_FSTATVFS.0, b"\x00\x01handle"),
+        );
+    }
 }
@@ -1,19 +1,39 @@
 #![forbid(unsafe_code)]
 
 use super::{
+    debug::{DebugBytes, DebugPath},
     file_attrs::FileAttrs,
-    {constants, seq_iter::SeqIter, visitor::impl_visitor, HandleOwned},
+    request::HashAlgorithm,
+    {
+        constants,
+        seq_iter::{from_packet_body, PacketDeserializer, SeqIter},
+        visitor::impl_visitor,
+        HandleOwned,
+    },
 };
 
-use std::{borrow::Cow, iter::FusedIterator, path::Path, str::from_utf8};
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    convert::TryInto,
+    fmt, io,
+    iter::{self, FusedIterator},
+    marker::PhantomData,
+    path::Path,
+    str::from_utf8,
+};
 
 use bitflags::bitflags;
 use openssh_sftp_protocol_error::{ErrMsg, ErrorCode};
 use serde::{
-    de::{Deserializer, Error, Unexpected},
-    Deserialize,
+    de::{DeserializeSeed, Deserializer, Error, SeqAccess, Unexpected, Visitor},
+    ser::{SerializeTuple, Serializer},
+    Deserialize, Serialize,
 };
 
+const MAX_FILENAME_LEN: usize = 64 * 1024;
+const MAX_LONGNAME_LEN: usize = 64 * 1024;
+
 bitflags! {
     /// The extension that the sftp-server supports.
     #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
@@ -27,15 +47,162 @@ bitflags! {
         const LIMITS= 1<< 6;
         const EXPAND_PATH= 1<< 7;
         const COPY_DATA= 1<< 8;
+        const USERS_GROUPS_BY_ID = 1 << 9;
+        const HOME_DIRECTORY = 1 << 10;
+        const CHECK_FILE = 1 << 11;
+    }
+}
+
+impl Extensions {
+    /// Return name of the extension if `self` contains exactly one extension.
+    pub fn name(self) -> Option<&'static str> {
+        let name = match self {
+            Extensions::POSIX_RENAME => constants::EXT_NAME_POSIX_RENAME,
+            Extensions::STATVFS => constants::EXT_NAME_STATVFS,
+            Extensions::FSTATVFS => constants::EXT_NAME_FSTATVFS,
+            Extensions::HARDLINK => constants::EXT_NAME_HARDLINK,
+            Extensions::FSYNC => constants::EXT_NAME_FSYNC,
+            Extensions::LSETSTAT => constants::EXT_NAME_LSETSTAT,
+            Extensions::LIMITS => constants::EXT_NAME_LIMITS,
+            Extensions::EXPAND_PATH => constants::EXT_NAME_EXPAND_PATH,
+            Extensions::COPY_DATA => constants::EXT_NAME_COPY_DATA,
+            Extensions::USERS_GROUPS_BY_ID => constants::EXT_NAME_USERS_GROUPS_BY_ID,
+            Extensions::HOME_DIRECTORY => constants::EXT_NAME_HOME_DIRECTORY,
+            Extensions::CHECK_FILE => constants::EXT_NAME_CHECK_FILE_HANDLE,
+            _ => return None,
+        };
+
+        Some(name.0)
+    }
+}
+
+/// Format as comma-separated extension names, e.g.
+/// `posix-rename@openssh.com, limits@openssh.com`.
+impl fmt::Display for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = self.iter().filter_map(Extensions::name);
+
+        if let Some(name) = names.next() {
+            f.write_str(name)?;
+        }
+        names.try_for_each(|name| write!(f, ", {}", name))
+    }
+}
+
+/// Version of the sftp protocol.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ProtocolVersion {
+    V3,
+    V4,
+    V5,
+    V6,
+    Unknown(u32),
+}
+
+impl ProtocolVersion {
+    pub const fn from_raw(version: u32) -> Self {
+        use ProtocolVersion::*;
+
+        match version {
+            3 => V3,
+            4 => V4,
+            5 => V5,
+            6 => V6,
+            version => Unknown(version),
+        }
+    }
+
+    pub const fn into_raw(self) -> u32 {
+        use ProtocolVersion::*;
+
+        match self {
+            V3 => 3,
+            V4 => 4,
+            V5 => 5,
+            V6 => 6,
+            Unknown(version) => version,
+        }
+    }
+}
+
+impl From<u32> for ProtocolVersion {
+    fn from(version: u32) -> Self {
+        Self::from_raw(version)
+    }
+}
+
+/// Payload of the `supported` extension, defined in sftp v5.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Supported {
+    /// Mask of `constants::SSH_FILEXFER_ATTR_*` the server supports.
+    pub attribute_mask: u32,
+    /// Mask of attribute bits the server supports.
+    pub attribute_bits: u32,
+    /// Mask of open flags the server supports.
+    pub open_flags: u32,
+    /// Mask of access flags the server supports.
+    pub access_mask: u32,
+    /// Maximum len the server would read in one request, `0` if unlimited.
+    pub max_read_size: u32,
+    /// Extensions supported by the server.
+    pub extension_names: Vec<String>,
+}
+
+impl Supported {
+    /// * `data` - the extension data of `supported`.
+    pub fn from_bytes(data: &[u8]) -> ssh_format::Result<Self> {
+        let de = &mut ssh_format::Deserializer::from_bytes(data);
+
+        let mut supported = Supported {
+            attribute_mask: u32::deserialize(&mut *de)?,
+            attribute_bits: u32::deserialize(&mut *de)?,
+            open_flags: u32::deserialize(&mut *de)?,
+            access_mask: u32::deserialize(&mut *de)?,
+            max_read_size: u32::deserialize(&mut *de)?,
+            extension_names: Vec::new(),
+        };
+
+        while de.has_remaining_data() {
+            supported
+                .extension_names
+                .push(String::deserialize(&mut *de)?);
+        }
+
+        Ok(supported)
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct ServerVersion {
     pub version: u32,
     pub extensions: Extensions,
+
+    /// Set if the server sends the `supported` extension.
+    ///
+    /// [`ServerVersion::deserialize`] fails if its data is malformed.
+    pub supported: Option<Supported>,
+
+    /// Every `(name, revision)` pair advertised by the server in order,
+    /// including the ones recognized in `extensions` and `supported`.
+    ///
+    /// Names and revisions that are not utf-8, e.g. the binary data of
+    /// `supported`, are converted lossily.
+    pub raw_extensions: Vec<(String, String)>,
 }
 impl ServerVersion {
+    /// Maximum number of extension pairs [`ServerVersion::deserialize`]
+    /// would process.
+    ///
+    /// openssh-portable advertises less than 20 extensions, so this limit
+    /// is only hit by a malicious or broken server, in which case an error
+    /// is returned.
+    pub const MAX_EXTENSIONS: usize = 1024;
+
+    /// Return `self.version` as [`ProtocolVersion`].
+    pub const fn protocol_version(&self) -> ProtocolVersion {
+        ProtocolVersion::from_raw(self.version)
+    }
+
     /// * `bytes` - should not include the initial 4-byte which server
     ///   as the length of the whole packet.
     pub fn deserialize<'de, It>(
@@ -52,8 +219,16 @@ impl ServerVersion {
         let version = u32::deserialize(&mut *de)?;
 
         let mut extensions = Extensions::default();
+        let mut supported = None;
+        let mut raw_extensions = Vec::new();
+        let mut extensions_cnt: usize = 0;
 
         while de.has_remaining_data() {
+            if extensions_cnt == Self::MAX_EXTENSIONS {
+                return Err(ssh_format::Error::custom("Too many extensions"));
+            }
+            extensions_cnt += 1;
+
             // sftp v3 does not specify the encoding of extension names and revisions.
             //
             // Read both name and revision before continue parsing them
@@ -62,6 +237,17 @@ impl ServerVersion {
             let name = Cow::<'_, [u8]>::deserialize(&mut *de)?;
             let revision = Cow::<'_, [u8]>::deserialize(&mut *de)?;
 
+            raw_extensions.push((
+                String::from_utf8_lossy(&name).into_owned(),
+                String::from_utf8_lossy(&revision).into_owned(),
+            ));
+
+            // Unlike other extensions, the data of `supported` is binary.
+            if &*name == b"supported" {
+                supported = Some(Supported::from_bytes(&revision)?);
+                continue;
+            }
+
             let optional_extension_pair = (|| {
                 let name = from_utf8(&name).ok()?;
                 let revision = from_utf8(&revision).ok()?;
@@ -104,6 +290,15 @@ impl ServerVersion {
                 constants::EXT_NAME_COPY_DATA => {
                     extensions |= Extensions::COPY_DATA;
                 }
+                constants::EXT_NAME_USERS_GROUPS_BY_ID => {
+                    extensions |= Extensions::USERS_GROUPS_BY_ID;
+                }
+                constants::EXT_NAME_HOME_DIRECTORY => {
+                    extensions |= Extensions::HOME_DIRECTORY;
+                }
+                constants::EXT_NAME_CHECK_FILE_HANDLE => {
+                    extensions |= Extensions::CHECK_FILE;
+                }
 
                 _ => (),
             }
@@ -112,13 +307,15 @@ impl ServerVersion {
         Ok(Self {
             version,
             extensions,
+            supported,
+            raw_extensions,
         })
     }
 }
 
 /// Payload of extended reply response when [`crate::request::RequestInner::Limits`]
 /// is sent.
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct Limits {
     pub packet_len: u64,
     pub read_len: u64,
@@ -126,28 +323,413 @@ pub struct Limits {
     pub open_handles: u64,
 }
 
+impl Limits {
+    /// Conservative limits to use when the server does not support
+    /// [`Extensions::LIMITS`]:
+    ///
+    ///  - `packet_len`: 256 KiB, `SFTP_MAX_MSG_LENGTH` in openssh, which is
+    ///    the maximum packet length accepted by openssh's sftp-server.
+    ///  - `read_len`: 32 KiB, the default request size of openssh's sftp
+    ///    client and the draft requires servers to support packets of at
+    ///    least 34000 bytes, which leaves enough space for the header.
+    ///  - `write_len`: 32 KiB, for the same reason as `read_len`.
+    ///  - `open_handles`: 0, i.e. no known limit, same as the meaning of
+    ///    `0` in the limits reply.
+    pub const DEFAULT: Limits = Limits {
+        packet_len: 256 * 1024,
+        read_len: 32 * 1024,
+        write_len: 32 * 1024,
+        open_handles: 0,
+    };
+}
+
+/// Return [`Limits::DEFAULT`].
+impl Default for Limits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Display as e.g. `read 32 KiB, write 32 KiB, packet 256 KiB, handles unlimited`,
+/// where `0` means no limit.
+impl fmt::Display for Limits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        struct Size(u64);
+
+        impl fmt::Display for Size {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                const UNITS: [(&str, u64); 3] =
+                    [("GiB", 1 << 30), ("MiB", 1 << 20), ("KiB", 1 << 10)];
+
+                match (
+                    self.0,
+                    UNITS.iter().find(|(_, unit)| self.0.is_multiple_of(*unit)),
+                ) {
+                    (0, _) => f.write_str("unlimited"),
+                    (size, Some((name, unit))) => write!(f, "{} {}", size / unit, name),
+                    (size, None) => write!(f, "{} B", size),
+                }
+            }
+        }
+
+        struct Count(u64);
+
+        impl fmt::Display for Count {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self.0 {
+                    0 => f.write_str("unlimited"),
+                    count => write!(f, "{}", count),
+                }
+            }
+        }
+
+        write!(
+            f,
+            "read {}, write {}, packet {}, handles {}",
+            Size(self.read_len),
+            Size(self.write_len),
+            Size(self.packet_len),
+            Count(self.open_handles),
+        )
+    }
+}
+
+impl_visitor!(Limits, LimitsVisitor, "Limits extended reply", seq, {
+    let mut iter = SeqIter::new(seq);
+    let mut next = || iter.get_next_or_truncated("limits reply", 4);
+
+    Ok(Limits {
+        packet_len: next()?,
+        read_len: next()?,
+        write_len: next()?,
+        open_handles: next()?,
+    })
+});
+
+/// Payload of extended reply response when [`crate::request::RequestInner::Statvfs`]
+/// or [`crate::request::RequestInner::Fstatvfs`] is sent.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct StatVfs {
+    /// File system block size
+    pub f_bsize: u64,
+    /// Fundamental fs block size
+    pub f_frsize: u64,
+    /// Number of blocks (unit f_frsize)
+    pub f_blocks: u64,
+    /// Free blocks in file system
+    pub f_bfree: u64,
+    /// Free blocks for non-root
+    pub f_bavail: u64,
+    /// Total file inodes
+    pub f_files: u64,
+    /// Free file inodes
+    pub f_ffree: u64,
+    /// Free file inodes for non-root
+    pub f_favail: u64,
+    /// File system id
+    pub f_sid: u64,
+    /// Bit mask of f_flag values
+    pub f_flag: u64,
+    /// Maximum filename length
+    pub f_namemax: u64,
+}
+
+impl_visitor!(StatVfs, StatVfsVisitor, "Statvfs extended reply", seq, {
+    let mut iter = SeqIter::new(seq);
+    let mut next = || iter.get_next_or_truncated("statvfs reply", 11);
+
+    Ok(StatVfs {
+        f_bsize: next()?,
+        f_frsize: next()?,
+        f_blocks: next()?,
+        f_bfree: next()?,
+        f_bavail: next()?,
+        f_files: next()?,
+        f_ffree: next()?,
+        f_favail: next()?,
+        f_sid: next()?,
+        f_flag: next()?,
+        f_namemax: next()?,
+    })
+});
+
+/// Payload of extended reply response when
+/// [`crate::request::RequestInner::UsersGroupsById`] is sent.
+///
+/// Names are in the same order as the ids in the request, and are empty
+/// if the id cannot be found on the server.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UsersGroupsById {
+    pub user_names: Vec<String>,
+    pub group_names: Vec<String>,
+}
+
+impl UsersGroupsById {
+    /// * `payload` - the bytes after `response_id` of the extended reply.
+    pub fn from_payload(payload: &[u8]) -> ssh_format::Result<Self> {
+        fn parse_names(mut names: &[u8]) -> ssh_format::Result<Vec<String>> {
+            let mut res = Vec::new();
+
+            while !names.is_empty() {
+                let (name, rest) = ssh_format::from_bytes::<&str>(names)?;
+                res.push(name.to_owned());
+                names = rest;
+            }
+
+            Ok(res)
+        }
+
+        let (user_names, rest) = ssh_format::from_bytes::<&[u8]>(payload)?;
+        let (group_names, _rest) = ssh_format::from_bytes::<&[u8]>(rest)?;
+
+        Ok(Self {
+            user_names: parse_names(user_names)?,
+            group_names: parse_names(group_names)?,
+        })
+    }
+}
+
+/// Payload of extended reply response to space-available requests.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SpaceAvailable {
+    /// Total size of the device in bytes
+    pub bytes_on_device: u64,
+    /// Free space on the device in bytes
+    pub unused_bytes_on_device: u64,
+    /// Total space available to the user in bytes
+    pub bytes_available_to_user: u64,
+    /// Free space available to the user in bytes
+    pub unused_bytes_available_to_user: u64,
+    /// Size of an allocation unit on the device in bytes
+    pub bytes_per_allocation_unit: u32,
+}
+
+impl_visitor!(
+    SpaceAvailable,
+    SpaceAvailableVisitor,
+    "Space available extended reply",
+    seq,
+    {
+        let mut iter = SeqIter::new(seq);
+        let name = "space-available reply";
+
+        Ok(SpaceAvailable {
+            bytes_on_device: iter.get_next_or_truncated(name, 5)?,
+            unused_bytes_on_device: iter.get_next_or_truncated(name, 5)?,
+            bytes_available_to_user: iter.get_next_or_truncated(name, 5)?,
+            unused_bytes_available_to_user: iter.get_next_or_truncated(name, 5)?,
+            bytes_per_allocation_unit: iter.get_next_or_truncated(name, 5)?,
+        })
+    }
+);
+
+/// Disk usage of a filesystem, which can be obtained from either
+/// [`StatVfs`] or [`SpaceAvailable`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct DiskUsage {
+    pub total_bytes: u64,
+    /// Bytes available to the (non-root) user.
+    pub available_bytes: u64,
+    pub used_bytes: u64,
+}
+
+impl From<StatVfs> for DiskUsage {
+    fn from(statvfs: StatVfs) -> Self {
+        let frsize = statvfs.f_frsize;
+
+        Self {
+            total_bytes: statvfs.f_blocks.saturating_mul(frsize),
+            available_bytes: statvfs.f_bavail.saturating_mul(frsize),
+            used_bytes: statvfs
+                .f_blocks
+                .saturating_sub(statvfs.f_bfree)
+                .saturating_mul(frsize),
+        }
+    }
+}
+
+impl From<SpaceAvailable> for DiskUsage {
+    fn from(space: SpaceAvailable) -> Self {
+        Self {
+            total_bytes: space.bytes_on_device,
+            available_bytes: space.unused_bytes_available_to_user,
+            used_bytes: space
+                .bytes_on_device
+                .saturating_sub(space.unused_bytes_on_device),
+        }
+    }
+}
+
+/// Payload of extended reply response to check-file requests.
+///
+/// The hash of each block is concatenated in `hashes`, or if the whole file
+/// is hashed as one block, then `hashes` contains exactly one hash.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CheckFileResponse<'a> {
+    /// Hash algorithm used by the server.
+    pub algorithm: &'a str,
+    pub hashes: &'a [u8],
+}
+
+impl<'a> CheckFileResponse<'a> {
+    /// * `payload` - the bytes after `response_id` of the extended reply.
+    pub fn from_payload(payload: &'a [u8]) -> ssh_format::Result<Self> {
+        let (name, rest) = ssh_format::from_bytes::<&str>(payload)?;
+        if name != "check-file" {
+            return Err(ssh_format::Error::custom("Unexpected extended reply"));
+        }
+
+        let (algorithm, hashes) = ssh_format::from_bytes::<&str>(rest)?;
+
+        Ok(Self { algorithm, hashes })
+    }
+
+    /// Return the algorithm used by the server, or `None` if the algorithm
+    /// is unknown.
+    pub fn hash_algorithm(&self) -> Option<HashAlgorithm> {
+        HashAlgorithm::from_name(self.algorithm)
+    }
+
+    /// Return length of the hash produced by `self.algorithm`, or `None`
+    /// if the algorithm is unknown.
+    pub fn digest_len(&self) -> Option<usize> {
+        self.hash_algorithm().map(HashAlgorithm::digest_len)
+    }
+
+    /// Return hash of each block.
+    ///
+    /// Return `None` if the algorithm is unknown or `hashes` is not
+    /// a multiple of [`CheckFileResponse::digest_len`].
+    pub fn blocks(&self) -> Option<impl ExactSizeIterator<Item = &'a [u8]>> {
+        let digest_len = self.digest_len()?;
+
+        if !self.hashes.len().is_multiple_of(digest_len) {
+            return None;
+        }
+
+        Some(self.hashes.chunks_exact(digest_len))
+    }
+}
+
 #[derive(Debug)]
-pub enum ResponseInner {
+pub enum ResponseInner<'a> {
     Status {
         status_code: StatusCode,
 
+        /// Some servers omit the err message and language tag entirely,
+        /// in which case this is set to [`ErrMsg::default`].
+        ///
+        /// Only [`Response::decode_body`] and [`Response::decode_packet`]
+        /// accept such status, since they know where the packet ends,
+        /// while `Deserialize` requires the err message to be present.
         err_msg: ErrMsg,
     },
 
     Handle(HandleOwned),
 
+    /// Handle followed by the attributes of the opened file, which v6
+    /// servers may append to the handle response of an `Open` request.
+    ///
+    /// v3 servers never send the attributes, so [`ResponseInner::Handle`]
+    /// is returned instead.
+    ///
+    /// Only returned by [`Response::decode_body`] and
+    /// [`Response::decode_packet`], since the attributes can only be
+    /// detected when the end of the packet is known.
+    HandleWithAttrs(HandleOwned, FileAttrs),
+
     Name(Box<[NameEntry]>),
 
     Attrs(FileAttrs),
+
+    /// Data read from the file, borrowed from the packet if possible.
+    Data(Cow<'a, [u8]>),
+
+    /// Extension specific payload following the response id, borrowed
+    /// from the packet.
+    ///
+    /// Only returned by [`Response::decode_body`] and
+    /// [`Response::decode_packet`], since the payload runs till the end of
+    /// the packet, `Deserialize` rejects extended replies as before.
+    ExtendedReply(Cow<'a, [u8]>),
+}
+
+impl ResponseInner<'_> {
+    /// Return (err_message, language_tag) of [`ResponseInner::Status`],
+    /// regardless of the status code, since servers may send informational
+    /// messages along with [`StatusCode::Success`].
+    ///
+    /// Return `None` if `self` is not a status response.
+    pub fn status_message(&self) -> Option<(&str, &str)> {
+        match self {
+            ResponseInner::Status { err_msg, .. } => Some(err_msg.get()),
+            _ => None,
+        }
+    }
+
+    /// Create [`ResponseInner::Status`] from `err`, with `err.to_string()`
+    /// as the err message and `"en"` as the language tag.
+    pub fn status_from_io_error(err: &io::Error) -> Self {
+        use io::ErrorKind::*;
+
+        let status_code = match err.kind() {
+            NotFound => StatusCode::Failure(ErrorCode::NoSuchFile),
+            PermissionDenied => StatusCode::Failure(ErrorCode::PermDenied),
+            Unsupported => StatusCode::Failure(ErrorCode::OpUnsupported),
+            InvalidInput | InvalidData => StatusCode::Failure(ErrorCode::BadMessage),
+            UnexpectedEof => StatusCode::Eof,
+            _ => StatusCode::Failure(ErrorCode::Failure),
+        };
+
+        ResponseInner::Status {
+            status_code,
+            err_msg: ErrMsg::new(&err.to_string(), "en"),
+        }
+    }
+
+    /// Copy the data borrowed from the packet, if any.
+    pub fn into_owned(self) -> ResponseInner<'static> {
+        use ResponseInner::*;
+
+        match self {
+            Status {
+                status_code,
+                err_msg,
+            } => Status {
+                status_code,
+                err_msg,
+            },
+            Handle(handle) => Handle(handle),
+            HandleWithAttrs(handle, attrs) => HandleWithAttrs(handle, attrs),
+            Name(entries) => Name(entries),
+            Attrs(attrs) => Attrs(attrs),
+            Data(data) => Data(Cow::Owned(data.into_owned())),
+            ExtendedReply(payload) => ExtendedReply(Cow::Owned(payload.into_owned())),
+        }
+    }
+}
+
+/// Returned by [`Response::check_read_len`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ReadLen {
+    /// Received exactly the requested length.
+    Full,
+
+    /// Received less than requested.
+    ///
+    /// The offset of the next read should only be advanced by this length.
+    /// It does not necessarily mean the end of file is reached, the server
+    /// will respond with [`StatusCode::Eof`] once that happens.
+    Short(u32),
 }
 
 #[derive(Debug)]
-pub struct Response {
+pub struct Response<'a> {
     pub response_id: u32,
-    pub response_inner: ResponseInner,
+    pub response_inner: ResponseInner<'a>,
 }
 
-impl Response {
+impl<'a> Response<'a> {
     /// Return true if the response is a data response.
     pub fn is_data(packet_type: u8) -> bool {
         packet_type == constants::SSH_FXP_DATA
@@ -157,106 +739,1497 @@ impl Response {
     pub fn is_extended_reply(packet_type: u8) -> bool {
         packet_type == constants::SSH_FXP_EXTENDED_REPLY
     }
-}
 
-impl_visitor!(
-    Response,
-    ResponseVisitor,
-    "Expects a u8 type and payload",
-    seq,
-    {
-        use constants::*;
-        use ResponseInner::*;
+    /// Check the length of data received in a data response against
+    /// `requested_len`, the length of the read request.
+    ///
+    /// Return an error if the server sent more data than requested.
+    pub fn check_read_len(requested_len: u32, data_len: usize) -> ssh_format::Result<ReadLen> {
+        match data_len.cmp(&(requested_len as usize)) {
+            Ordering::Equal => Ok(ReadLen::Full),
+            // data_len < requested_len <= u32::MAX
+            Ordering::Less => Ok(ReadLen::Short(data_len as u32)),
+            Ordering::Greater => Err(ssh_format::Error::custom(format_args!(
+                "Server sent {} bytes of data but only {} bytes are requested",
+                data_len, requested_len
+            ))),
+        }
+    }
 
-        let mut iter = SeqIter::new(seq);
+    /// Decode the response from `body`.
+    ///
+    /// * `body` - should not include the initial 4-byte which serves
+    ///   as the length of the whole packet and must contain exactly one
+    ///   response.
+    ///
+    /// Use [`Response::decode_packet`] if `body` still has the length prefix.
+    ///
+    /// [`ResponseInner::Data`] and [`ResponseInner::ExtendedReply`] borrow
+    /// from `body`.
+    pub fn decode_body(body: &'a [u8]) -> ssh_format::Result<Self> {
+        if body.first() == Some(&constants::SSH_FXP_EXTENDED_REPLY) {
+            let ((_packet_type, response_id), payload) = ssh_format::from_bytes::<(u8, u32)>(body)?;
 
-        let discriminant: u8 = iter.get_next()?;
-        let response_id: u32 = iter.get_next()?;
+            return Ok(Response {
+                response_id,
+                response_inner: ResponseInner::ExtendedReply(Cow::Borrowed(payload)),
+            });
+        }
 
-        let response_inner = match discriminant {
-            SSH_FXP_STATUS => Status {
-                status_code: iter.get_next()?,
-                err_msg: iter.get_next()?,
-            },
+        from_packet_body(body, ResponseVisitor::new(true), "response")
+    }
 
-            SSH_FXP_HANDLE => Handle(HandleOwned(iter.get_next()?)),
+    /// Decode the response from `packet`.
+    ///
+    /// * `packet` - must include the initial 4-byte which serves as the
+    ///   length of the whole packet, which must match the length of the rest
+    ///   of `packet`.
+    ///
+    /// Use [`Response::decode_body`] if the length prefix has already been
+    /// stripped.
+    pub fn decode_packet(packet: &'a [u8]) -> ssh_format::Result<Self> {
+        let (len, body) = ssh_format::from_bytes::<u32>(packet)?;
 
-            SSH_FXP_NAME => {
-                let len: u32 = iter.get_next()?;
-                let len = len as usize;
-                let mut entries = Vec::<NameEntry>::with_capacity(len);
+        if len as usize != body.len() {
+            return Err(ssh_format::Error::custom(
+                "Length prefix does not match the length of the packet",
+            ));
+        }
 
-                for _ in 0..len {
-                    let filename: Box<Path> = iter.get_next()?;
-                    let _longname: &[u8] = iter.get_next()?;
-                    let attrs: FileAttrs = iter.get_next()?;
+        Self::decode_body(body)
+    }
 
-                    entries.push(NameEntry { filename, attrs });
-                }
+    /// Decode the data response from `body` without copying the data.
+    ///
+    /// Return the response id and the data.
+    ///
+    /// * `body` - should not include the initial 4-byte which serves
+    ///   as the length of the whole packet and must contain exactly one
+    ///   data response.
+    pub fn decode_data(body: &[u8]) -> ssh_format::Result<(u32, &[u8])> {
+        let de = &mut ssh_format::Deserializer::from_bytes(body);
 
-                Name(entries.into_boxed_slice())
-            }
+        let packet_type = u8::deserialize(&mut *de)?;
+        if !Self::is_data(packet_type) {
+            return Err(ssh_format::Error::custom("Unexpected response"));
+        }
 
-            SSH_FXP_ATTRS => Attrs(iter.get_next()?),
+        let response_id = u32::deserialize(&mut *de)?;
+        let data = <&[u8]>::deserialize(&mut *de)?;
 
-            _ => {
-                return Err(Error::invalid_value(
-                    Unexpected::Unsigned(discriminant as u64),
-                    &"Invalid packet type",
-                ))
-            }
-        };
+        if de.has_remaining_data() {
+            return Err(ssh_format::Error::custom(
+                "Trailing bytes after the response",
+            ));
+        }
 
-        Ok(Response {
-            response_id,
-            response_inner,
-        })
+        Ok((response_id, data))
     }
-);
-
-#[derive(Debug, Copy, Clone)]
-pub enum StatusCode {
-    Success,
-    Failure(ErrorCode),
 
-    /// Indicates end-of-file condition.
+    /// Decode the extended reply from `body`, with payload `T`, which
+    /// depends on the request it responds to, e.g. [`Limits`] for
+    /// [`crate::request::RequestInner::Limits`].
     ///
-    /// For RequestInner::Read it means that no more data is available in the file,
-    /// and for RequestInner::Readdir it indicates that no more files are contained
-    /// in the directory.
-    Eof,
-}
-impl<'de> Deserialize<'de> for StatusCode {
-    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
-        use constants::*;
-        use ErrorCode::*;
-
-        let discriminant = <u32 as Deserialize>::deserialize(deserializer)?;
+    /// Return the response id and the payload.
+    ///
+    /// * `body` - should not include the initial 4-byte which serves
+    ///   as the length of the whole packet and must contain exactly one
+    ///   extended reply.
+    pub fn parse_extended_reply<'de, T: Deserialize<'de>>(
+        body: &'de [u8],
+    ) -> ssh_format::Result<(u32, T)> {
+        let de = &mut ssh_format::Deserializer::from_bytes(body);
 
-        match discriminant {
-            SSH_FX_OK => Ok(StatusCode::Success),
-            SSH_FX_EOF => Ok(StatusCode::Eof),
-            SSH_FX_NO_SUCH_FILE => Ok(StatusCode::Failure(NoSuchFile)),
-            SSH_FX_PERMISSION_DENIED => Ok(StatusCode::Failure(PermDenied)),
-            SSH_FX_FAILURE => Ok(StatusCode::Failure(Failure)),
-            SSH_FX_BAD_MESSAGE => Ok(StatusCode::Failure(BadMessage)),
-            SSH_FX_OP_UNSUPPORTED => Ok(StatusCode::Failure(OpUnsupported)),
+        let packet_type = u8::deserialize(&mut *de)?;
+        if !Self::is_extended_reply(packet_type) {
+            return Err(ssh_format::Error::custom("Unexpected response"));
+        }
 
-            SSH_FX_NO_CONNECTION | SSH_FX_CONNECTION_LOST => Err(Error::invalid_value(
-                Unexpected::Unsigned(discriminant as u64),
-                &"Server MUST NOT return SSH_FX_NO_CONNECTION or SSH_FX_CONNECTION_LOST \
-                for they are pseudo-error that can only be generated locally.",
-            )),
+        let response_id = u32::deserialize(&mut *de)?;
+        let payload = T::deserialize(PacketDeserializer(&mut *de))?;
 
-            _ => Ok(StatusCode::Failure(Unknown)),
+        if de.has_remaining_data() {
+            return Err(ssh_format::Error::custom(
+                "Trailing bytes after the response",
+            ));
         }
-    }
-}
 
-/// Entry in [`ResponseInner::Name`]
-#[derive(Debug, Clone)]
-pub struct NameEntry {
-    pub filename: Box<Path>,
+        Ok((response_id, payload))
+    }
+
+    /// Deserialize the data of a data response, i.e. the rest of the
+    /// response after the response id.
+    ///
+    /// The data is borrowed from the input of `deserializer` if possible.
+    pub fn deserialize_data<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Cow<'de, [u8]>, D::Error> {
+        deserializer.deserialize_bytes(DataVisitor)
+    }
+
+    /// Decode [`ResponseInner::Name`] from `body` and invoke `f` on every
+    /// [`NameEntry`] without collecting them.
+    ///
+    /// Return the response id on success.
+    ///
+    /// * `body` - should not include the initial 4-byte which serves
+    ///   as the length of the whole packet.
+    pub fn for_each_name_entry(
+        body: &[u8],
+        mut f: impl FnMut(NameEntry),
+    ) -> ssh_format::Result<u32> {
+        let (response_id, entries) = Self::name_entries(body)?;
+
+        for entry in entries {
+            f(entry?);
+        }
+
+        Ok(response_id)
+    }
+
+    /// Decode the header of [`ResponseInner::Name`] from `body` and return
+    /// the response id and an iterator decoding [`NameEntry`] lazily, so
+    /// that large directories can be processed without collecting every
+    /// entry first.
+    ///
+    /// * `body` - should not include the initial 4-byte which serves
+    ///   as the length of the whole packet.
+    pub fn name_entries(body: &[u8]) -> ssh_format::Result<(u32, NameEntries<'_>)> {
+        let mut de = ssh_format::Deserializer::from_bytes(body);
+
+        let packet_type = u8::deserialize(&mut de)?;
+        if packet_type != constants::SSH_FXP_NAME {
+            return Err(ssh_format::Error::custom("Unexpected response"));
+        }
+
+        let response_id = u32::deserialize(&mut de)?;
+        let remaining = u32::deserialize(&mut de)?;
+
+        Ok((response_id, NameEntries { de, remaining }))
+    }
+}
+
+/// Iterator over [`NameEntry`] returned by [`Response::name_entries`].
+///
+/// Iteration stops after the first error.
+pub struct NameEntries<'de> {
+    de: ssh_format::Deserializer<'de, iter::Empty<&'de [u8]>>,
+    remaining: u32,
+}
+
+impl fmt::Debug for NameEntries<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NameEntries")
+            .field("remaining", &self.remaining)
+            .finish()
+    }
+}
+
+impl NameEntries<'_> {
+    /// Return number of entries that are yet to be decoded.
+    pub const fn remaining(&self) -> u32 {
+        self.remaining
+    }
+}
+
+impl Iterator for NameEntries<'_> {
+    type Item = ssh_format::Result<NameEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let res = NameEntry::deserialize(&mut self.de);
+        self.remaining = if res.is_ok() { self.remaining - 1 } else { 0 };
+
+        Some(res)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.remaining as usize;
+        (remaining.min(1), Some(remaining))
+    }
+}
+
+impl FusedIterator for NameEntries<'_> {}
+
+struct DataVisitor;
+
+impl<'de> Visitor<'de> for DataVisitor {
+    type Value = Cow<'de, [u8]>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("data of a data response")
+    }
+
+    fn visit_borrowed_bytes<E: Error>(self, v: &'de [u8]) -> Result<Self::Value, E> {
+        Ok(Cow::Borrowed(v))
+    }
+
+    fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(Cow::Owned(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E: Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(Cow::Owned(v))
+    }
+}
+
+/// Deserialize data of [`ResponseInner::Data`] via [`Response::deserialize_data`].
+struct ResponseData<'de>(Cow<'de, [u8]>);
+
+impl<'de> Deserialize<'de> for ResponseData<'de> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Response::deserialize_data(deserializer).map(ResponseData)
+    }
+}
+
+/// Trailing optional fields, e.g. the attributes of
+/// [`ResponseInner::HandleWithAttrs`], can only be detected by
+/// [`Response::decode_body`], so they are never read here, which keeps
+/// the following packets intact when deserializing from a stream.
+impl<'de: 'a, 'a> Deserialize<'de> for Response<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        ResponseVisitor::new(false).deserialize(deserializer)
+    }
+}
+
+struct ResponseVisitor<'a> {
+    /// Whether the sequence ends at the end of the packet, i.e. whether
+    /// it is visited via [`from_packet_body`], so that trailing optional
+    /// fields can be read.
+    packet: bool,
+    phantom: PhantomData<Response<'a>>,
+}
+
+impl ResponseVisitor<'_> {
+    const fn new(packet: bool) -> Self {
+        Self {
+            packet,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'de: 'a, 'a> DeserializeSeed<'de> for ResponseVisitor<'a> {
+    type Value = Response<'a>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        // Pass a dummy size here since ssh_format doesn't care
+        deserializer.deserialize_tuple(u32::MAX as usize, self)
+    }
+}
+
+impl<'de: 'a, 'a> Visitor<'de> for ResponseVisitor<'a> {
+    type Value = Response<'a>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("Expects a u8 type and payload")
+    }
+
+    fn visit_seq<V: SeqAccess<'de>>(self, seq: V) -> Result<Self::Value, V::Error> {
+        use constants::*;
+        use ResponseInner::*;
+
+        let mut iter = SeqIter::new(seq);
+
+        let discriminant: u8 = iter.get_next()?;
+        let response_id: u32 = iter.get_next()?;
+
+        let response_inner = match discriminant {
+            SSH_FXP_STATUS => Status {
+                status_code: iter.get_next()?,
+                err_msg: if self.packet {
+                    iter.get_next_optional()?.unwrap_or_default()
+                } else {
+                    iter.get_next()?
+                },
+            },
+
+            SSH_FXP_HANDLE => {
+                let handle =
+                    HandleOwned(iter.get_next_bytes_capped(crate::Handle::MAX_LEN)?.into());
+
+                let attrs = if self.packet {
+                    iter.get_next_optional()?
+                } else {
+                    None
+                };
+
+                match attrs {
+                    Some(attrs) => HandleWithAttrs(handle, attrs),
+                    None => Handle(handle),
+                }
+            }
+
+            SSH_FXP_NAME => {
+                let len: u32 = iter.get_next()?;
+                let len = len as usize;
+                let mut entries = Vec::<NameEntry>::with_capacity(len);
+
+                for _ in 0..len {
+                    entries.push(iter.get_next()?);
+                }
+
+                Name(entries.into_boxed_slice())
+            }
+
+            SSH_FXP_ATTRS => Attrs(iter.get_next()?),
+
+            SSH_FXP_DATA => Data(iter.get_next::<ResponseData<'de>>()?.0),
+
+            _ => {
+                return Err(Error::invalid_value(
+                    Unexpected::Unsigned(discriminant as u64),
+                    &"Invalid packet type",
+                ))
+            }
+        };
+
+        Ok(Response {
+            response_id,
+            response_inner,
+        })
+    }
+}
+
+/// Serialize the response sent by the server, for implementing sftp
+/// server.
+impl Serialize for Response<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use ResponseInner::*;
+
+        let response_id = self.response_id;
+
+        match &self.response_inner {
+            Status {
+                status_code,
+                err_msg,
+            } => {
+                (constants::SSH_FXP_STATUS, response_id, status_code, err_msg).serialize(serializer)
+            }
+            Handle(handle) => {
+                (constants::SSH_FXP_HANDLE, response_id, &**handle).serialize(serializer)
+            }
+            HandleWithAttrs(handle, attrs) => {
+                (constants::SSH_FXP_HANDLE, response_id, &**handle, attrs).serialize(serializer)
+            }
+            Name(entries) => {
+                let len: u32 = entries
+                    .len()
+                    .try_into()
+                    .map_err(|_| serde::ser::Error::custom("Too many entries"))?;
+
+                // dummy size since ssh_format doesn't care
+                let mut tuple_serializer = serializer.serialize_tuple(1)?;
+
+                tuple_serializer.serialize_element(&constants::SSH_FXP_NAME)?;
+                tuple_serializer.serialize_element(&response_id)?;
+                tuple_serializer.serialize_element(&len)?;
+                for entry in entries.iter() {
+                    tuple_serializer.serialize_element(entry)?;
+                }
+
+                tuple_serializer.end()
+            }
+            Attrs(attrs) => (constants::SSH_FXP_ATTRS, response_id, attrs).serialize(serializer),
+            Data(data) => (constants::SSH_FXP_DATA, response_id, &**data).serialize(serializer),
+            ExtendedReply(payload) => {
+                // dummy size since ssh_format doesn't care
+                let mut tuple_serializer = serializer.serialize_tuple(1)?;
+
+                tuple_serializer.serialize_element(&constants::SSH_FXP_EXTENDED_REPLY)?;
+                tuple_serializer.serialize_element(&response_id)?;
+                // The payload is not length-prefixed.
+                for byte in payload.iter() {
+                    tuple_serializer.serialize_element(byte)?;
+                }
+
+                tuple_serializer.end()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum StatusCode {
+    Success,
+    Failure(ErrorCode),
+
+    /// Indicates end-of-file condition.
+    ///
+    /// For RequestInner::Read it means that no more data is available in the file,
+    /// and for RequestInner::Readdir it indicates that no more files are contained
+    /// in the directory.
+    Eof,
+}
+impl<'de> Deserialize<'de> for StatusCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use constants::*;
+        use ErrorCode::*;
+
+        let discriminant = <u32 as Deserialize>::deserialize(deserializer)?;
+
+        match discriminant {
+            SSH_FX_OK => Ok(StatusCode::Success),
+            SSH_FX_EOF => Ok(StatusCode::Eof),
+            SSH_FX_NO_SUCH_FILE => Ok(StatusCode::Failure(NoSuchFile)),
+            SSH_FX_PERMISSION_DENIED => Ok(StatusCode::Failure(PermDenied)),
+            SSH_FX_FAILURE => Ok(StatusCode::Failure(Failure)),
+            SSH_FX_BAD_MESSAGE => Ok(StatusCode::Failure(BadMessage)),
+            SSH_FX_OP_UNSUPPORTED => Ok(StatusCode::Failure(OpUnsupported)),
+            SSH_FX_INVALID_HANDLE => Ok(StatusCode::Failure(InvalidHandle)),
+            SSH_FX_NO_SUCH_PATH => Ok(StatusCode::Failure(NoSuchPath)),
+            SSH_FX_FILE_ALREADY_EXISTS => Ok(StatusCode::Failure(FileAlreadyExists)),
+            SSH_FX_WRITE_PROTECT => Ok(StatusCode::Failure(WriteProtect)),
+            SSH_FX_NO_SPACE_ON_FILESYSTEM => Ok(StatusCode::Failure(NoSpaceOnFilesystem)),
+            SSH_FX_QUOTA_EXCEEDED => Ok(StatusCode::Failure(QuotaExceeded)),
+
+            SSH_FX_NO_CONNECTION | SSH_FX_CONNECTION_LOST => Err(Error::invalid_value(
+                Unexpected::Unsigned(discriminant as u64),
+                &"Server MUST NOT return SSH_FX_NO_CONNECTION or SSH_FX_CONNECTION_LOST \
+                for they are pseudo-error that can only be generated locally.",
+            )),
+
+            _ => Ok(StatusCode::Failure(Unknown(discriminant))),
+        }
+    }
+}
+
+/// Serialize as the `SSH_FX_*` code, see [`StatusCode::to_raw`].
+impl Serialize for StatusCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_raw().serialize(serializer)
+    }
+}
+
+impl StatusCode {
+    /// Return the `SSH_FX_*` code sent on the wire.
+    pub const fn to_raw(self) -> u32 {
+        use constants::*;
+        use ErrorCode::*;
+
+        match self {
+            StatusCode::Success => SSH_FX_OK,
+            StatusCode::Eof => SSH_FX_EOF,
+            StatusCode::Failure(err_code) => match err_code {
+                NoSuchFile => SSH_FX_NO_SUCH_FILE,
+                PermDenied => SSH_FX_PERMISSION_DENIED,
+                Failure => SSH_FX_FAILURE,
+                BadMessage => SSH_FX_BAD_MESSAGE,
+                OpUnsupported => SSH_FX_OP_UNSUPPORTED,
+                InvalidHandle => SSH_FX_INVALID_HANDLE,
+                NoSuchPath => SSH_FX_NO_SUCH_PATH,
+                FileAlreadyExists => SSH_FX_FILE_ALREADY_EXISTS,
+                WriteProtect => SSH_FX_WRITE_PROTECT,
+                NoSpaceOnFilesystem => SSH_FX_NO_SPACE_ON_FILESYSTEM,
+                QuotaExceeded => SSH_FX_QUOTA_EXCEEDED,
+                Unknown(code) => code,
+                // ErrorCode is non_exhaustive
+                _ => SSH_FX_FAILURE,
+            },
+        }
+    }
+
+    /// Convert `self` into [`io::Error`], with `err_msg` as the error
+    /// message, or `self.to_string()` if `err_msg` is empty.
+    ///
+    /// [`StatusCode::Eof`] is converted to [`io::ErrorKind::UnexpectedEof`].
+    pub fn into_io_error(self, err_msg: &ErrMsg) -> io::Error {
+        let kind = match self {
+            StatusCode::Success => io::ErrorKind::Other,
+            StatusCode::Failure(err_code) => err_code.into(),
+            StatusCode::Eof => io::ErrorKind::UnexpectedEof,
+        };
+
+        match err_msg.get().0 {
+            "" => io::Error::new(kind, self.to_string()),
+            msg => io::Error::new(kind, msg),
+        }
+    }
+
+    /// Return the raw code if the server sent a status code that
+    /// is not recognized, so that it can be logged or recorded.
+    pub fn unknown_code(&self) -> Option<u32> {
+        match self {
+            StatusCode::Failure(ErrorCode::Unknown(code)) => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatusCode::Success => f.write_str("success"),
+            StatusCode::Failure(err_code) => fmt::Display::fmt(err_code, f),
+            StatusCode::Eof => f.write_str("unexpected end of file"),
+        }
+    }
+}
+
+impl std::error::Error for StatusCode {}
+
+impl From<StatusCode> for io::Error {
+    fn from(status_code: StatusCode) -> Self {
+        status_code.into_io_error(&ErrMsg::default())
+    }
+}
+
+/// Entry in [`ResponseInner::Name`]
+///
+/// `NameEntry` owns all of its data and does not borrow from the buffer
+/// it is decoded from, so cloning it is always a deep copy and entries
+/// from multiple responses can be collected together.
+#[derive(Clone)]
+pub struct NameEntry {
+    pub filename: Box<Path>,
+
+    /// Human-readable description of the entry in the format of `ls -l`,
+    /// which is not specified by the protocol and might not be utf-8.
+    ///
+    /// It is empty for [`crate::request::RequestInner::Realpath`] and
+    /// [`crate::request::RequestInner::Readlink`] in openssh.
+    pub longname: Box<[u8]>,
 
     pub attrs: FileAttrs,
 }
+
+impl NameEntry {
+    /// Return `self`, since `NameEntry` is already owned.
+    ///
+    /// Provided so that code converting borrowed entries into owned ones
+    /// can treat all entries uniformly.
+    pub fn into_owned(self) -> NameEntry {
+        self
+    }
+
+    /// Return [`NameEntry::longname`] if it is valid utf-8.
+    pub fn longname_str(&self) -> Option<&str> {
+        from_utf8(&self.longname).ok()
+    }
+}
+
+impl_visitor!(NameEntry, NameEntryVisitor, "Name entry", seq, {
+    let mut iter = SeqIter::new(seq);
+
+    let filename = iter.get_next_bytes_capped(MAX_FILENAME_LEN)?;
+    let filename = String::from_utf8(filename).map_err(|err| {
+        Error::invalid_value(Unexpected::Bytes(err.as_bytes()), &"utf-8 filename")
+    })?;
+
+    let longname = iter.get_next_bytes_capped(MAX_LONGNAME_LEN)?;
+    let attrs = iter.get_next()?;
+
+    Ok(NameEntry {
+        filename: Path::new(&filename).into(),
+        longname: longname.into_boxed_slice(),
+        attrs,
+    })
+});
+
+impl Serialize for NameEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (&*self.filename, &*self.longname, &self.attrs).serialize(serializer)
+    }
+}
+
+impl fmt::Debug for NameEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NameEntry")
+            .field("filename", &DebugPath(&self.filename))
+            .field("longname", &DebugBytes(&self.longname))
+            .field("attrs", &self.attrs)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Append `bytes` prefixed with its length as `u32`.
+    fn push_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+        buf.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+        buf.extend_from_slice(bytes);
+    }
+
+    fn status_body(status_code: u32, err_msg: Option<(&str, &str)>) -> Vec<u8> {
+        let mut body = vec![constants::SSH_FXP_STATUS];
+        body.extend_from_slice(&0x1234_u32.to_be_bytes());
+        body.extend_from_slice(&status_code.to_be_bytes());
+
+        if let Some((msg, lang)) = err_msg {
+            for s in [msg, lang] {
+                push_bytes(&mut body, s.as_bytes());
+            }
+        }
+
+        body
+    }
+
+    fn server_version_body<'a>(
+        extensions: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Vec<u8> {
+        let mut body = vec![constants::SSH_FXP_VERSION];
+        body.extend_from_slice(&3_u32.to_be_bytes());
+
+        for (name, revision) in extensions {
+            for s in [name, revision] {
+                push_bytes(&mut body, s.as_bytes());
+            }
+        }
+
+        body
+    }
+
+    #[test]
+    fn test_server_version() {
+        let body = server_version_body([
+            ("posix-rename@openssh.com", "1"),
+            ("limits@openssh.com", "1"),
+            ("home-directory", "1"),
+            ("unknown@example.com", "1"),
+        ]);
+        let server_version =
+            ServerVersion::deserialize(&mut ssh_format::Deserializer::from_bytes(&body)).unwrap();
+
+        assert_eq!(server_version.version, 3);
+        assert_eq!(server_version.protocol_version(), ProtocolVersion::V3);
+        assert_eq!(
+            server_version.extensions,
+            Extensions::POSIX_RENAME | Extensions::LIMITS | Extensions::HOME_DIRECTORY
+        );
+        assert_eq!(server_version.supported, None);
+        assert_eq!(
+            server_version.raw_extensions,
+            [
+                ("posix-rename@openssh.com", "1"),
+                ("limits@openssh.com", "1"),
+                ("home-directory", "1"),
+                ("unknown@example.com", "1"),
+            ]
+            .map(|(name, revision)| (name.to_owned(), revision.to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_extensions_display() {
+        assert_eq!(Extensions::empty().to_string(), "");
+        assert_eq!(
+            (Extensions::POSIX_RENAME | Extensions::HARDLINK | Extensions::LIMITS).to_string(),
+            "posix-rename@openssh.com, hardlink@openssh.com, limits@openssh.com"
+        );
+    }
+
+    #[test]
+    fn test_server_version_supported() {
+        let mut data = Vec::new();
+        for field in [1_u32, 2, 3, 4, 5] {
+            data.extend_from_slice(&field.to_be_bytes());
+        }
+        for s in ["text-seek", "space-available"] {
+            push_bytes(&mut data, s.as_bytes());
+        }
+
+        let mut body = server_version_body([("fsync@openssh.com", "1")]);
+        for s in [&b"supported"[..], &data] {
+            push_bytes(&mut body, s);
+        }
+
+        let server_version =
+            ServerVersion::deserialize(&mut ssh_format::Deserializer::from_bytes(&body)).unwrap();
+
+        assert_eq!(server_version.extensions, Extensions::FSYNC);
+        assert_eq!(
+            server_version.raw_extensions,
+            [
+                ("fsync@openssh.com".to_owned(), "1".to_owned()),
+                (
+                    "supported".to_owned(),
+                    String::from_utf8_lossy(&data).into_owned()
+                ),
+            ]
+        );
+        assert_eq!(
+            server_version.supported.unwrap(),
+            Supported {
+                attribute_mask: 1,
+                attribute_bits: 2,
+                open_flags: 3,
+                access_mask: 4,
+                max_read_size: 5,
+                extension_names: vec!["text-seek".into(), "space-available".into()],
+            }
+        );
+
+        // Malformed supported is an error instead of being dropped
+        let mut body = server_version_body([("fsync@openssh.com", "1")]);
+        for s in [&b"supported"[..], &data[..10]] {
+            push_bytes(&mut body, s);
+        }
+        ServerVersion::deserialize(&mut ssh_format::Deserializer::from_bytes(&body)).unwrap_err();
+    }
+
+    #[test]
+    fn test_server_version_too_many_extensions() {
+        let extensions = || std::iter::repeat(("unknown@example.com", "1"));
+
+        let body = server_version_body(extensions().take(ServerVersion::MAX_EXTENSIONS));
+        ServerVersion::deserialize(&mut ssh_format::Deserializer::from_bytes(&body)).unwrap();
+
+        let body = server_version_body(extensions().take(ServerVersion::MAX_EXTENSIONS + 1));
+        ServerVersion::deserialize(&mut ssh_format::Deserializer::from_bytes(&body)).unwrap_err();
+    }
+
+    #[test]
+    fn test_check_file_response() {
+        let mut payload = Vec::new();
+        for s in ["check-file", "md5"] {
+            push_bytes(&mut payload, s.as_bytes());
+        }
+        payload.extend((0..48).map(|i| i as u8));
+
+        let response = CheckFileResponse::from_payload(&payload).unwrap();
+        assert_eq!(response.algorithm, "md5");
+
+        let blocks: Vec<_> = response.blocks().unwrap().collect();
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[1], &payload[payload.len() - 32..payload.len() - 16]);
+
+        // Whole file hash
+        payload.truncate(payload.len() - 32);
+        let response = CheckFileResponse::from_payload(&payload).unwrap();
+        assert_eq!(response.blocks().unwrap().count(), 1);
+
+        // Truncated hash
+        payload.pop();
+        let response = CheckFileResponse::from_payload(&payload).unwrap();
+        assert!(response.blocks().is_none());
+    }
+
+    #[test]
+    fn test_limits() {
+        let payload: Vec<u8> = [1_u64, 2, 3, 4]
+            .iter()
+            .flat_map(|field| field.to_be_bytes())
+            .collect();
+
+        let (limits, _) = ssh_format::from_bytes::<Limits>(&payload).unwrap();
+        assert_eq!(
+            limits,
+            Limits {
+                packet_len: 1,
+                read_len: 2,
+                write_len: 3,
+                open_handles: 4,
+            }
+        );
+
+        let err = ssh_format::from_bytes::<Limits>(&payload[..24]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "truncated limits reply, expected 4 fields but only got 3"
+        );
+    }
+
+    #[test]
+    fn test_users_groups_by_id() {
+        let mut payload = Vec::new();
+        payload.extend_from_slice(b"\0\0\0\x0d\0\0\0\x04root\0\0\0\x01a");
+        payload.extend_from_slice(b"\0\0\0\x04\0\0\0\0");
+
+        assert_eq!(
+            UsersGroupsById::from_payload(&payload).unwrap(),
+            UsersGroupsById {
+                user_names: vec!["root".to_owned(), "a".to_owned()],
+                group_names: vec![String::new()],
+            }
+        );
+
+        UsersGroupsById::from_payload(&payload[..10]).unwrap_err();
+    }
+
+    #[test]
+    fn test_statvfs() {
+        // Extended reply to statvfs@openssh.com for an ext4 filesystem
+        // mounted with nosuid.
+        #[rustfmt::skip]
+        let packet: &[u8] = &[
+            0x00, 0x00, 0x00, 0x5d, // packet length
+            0xc9, // SSH_FXP_EXTENDED_REPLY
+            0x00, 0x00, 0x00, 0x05, // response id
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x03, 0xa5, 0xe2, 0xc5,
+            0x00, 0x00, 0x00, 0x00, 0x01, 0xc9, 0xc3, 0x80,
+            0x00, 0x00, 0x00, 0x00, 0x01, 0x99, 0xe1, 0xd2,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0xee, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0xd5, 0x9f, 0x80,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0xd5, 0x9f, 0x80,
+            0x8c, 0x5b, 0x3f, 0xd2, 0xa0, 0xe1, 0x42, 0xf6,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff,
+        ];
+
+        assert_eq!(packet.len(), 4 + 0x5d);
+        assert!(Response::is_extended_reply(packet[4]));
+
+        let (statvfs, rest) = ssh_format::from_bytes::<StatVfs>(&packet[9..]).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(
+            statvfs,
+            StatVfs {
+                f_bsize: 4096,
+                f_frsize: 4096,
+                f_blocks: 61_203_141,
+                f_bfree: 30_000_000,
+                f_bavail: 26_862_034,
+                f_files: 15_597_568,
+                f_ffree: 14_000_000,
+                f_favail: 14_000_000,
+                f_sid: 0x8c5b_3fd2_a0e1_42f6,
+                f_flag: 2,
+                f_namemax: 255,
+            }
+        );
+
+        let err = ssh_format::from_bytes::<StatVfs>(&packet[9..packet.len() - 8]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "truncated statvfs reply, expected 11 fields but only got 10"
+        );
+    }
+
+    fn name_body(filenames: &[&str]) -> Vec<u8> {
+        let mut body = vec![constants::SSH_FXP_NAME];
+        body.extend_from_slice(&0x1234_u32.to_be_bytes());
+        body.extend_from_slice(&(filenames.len() as u32).to_be_bytes());
+
+        for filename in filenames {
+            for s in [filename, "longname"] {
+                push_bytes(&mut body, s.as_bytes());
+            }
+            // attrs with no flags set
+            body.extend_from_slice(&0_u32.to_be_bytes());
+        }
+
+        body
+    }
+
+    #[test]
+    fn test_for_each_name_entry() {
+        let body = name_body(&["a", "b", "c"]);
+
+        let mut filenames = Vec::new();
+        let response_id = Response::for_each_name_entry(&body, |entry| {
+            assert_eq!(&*entry.longname, b"longname");
+            assert_eq!(entry.longname_str(), Some("longname"));
+            filenames.push(entry.filename);
+        })
+        .unwrap();
+
+        assert_eq!(response_id, 0x1234);
+        assert_eq!(filenames, ["a", "b", "c"].map(|s| Box::from(Path::new(s))));
+    }
+
+    #[test]
+    fn test_name_entry_debug() {
+        let entry = NameEntry {
+            filename: Path::new("secret").into(),
+            longname: (*b"-rw-r--r-- 1 user user 0 Jan 1 00:00 secret").into(),
+            attrs: FileAttrs::new(),
+        };
+        let debug = format!("{:?}", entry);
+
+        if cfg!(feature = "redact-debug") {
+            assert!(!debug.contains("secret"), "{}", debug);
+            assert!(debug.contains("<redacted 43 bytes>"), "{}", debug);
+        } else {
+            assert!(debug.contains("\"secret\""), "{}", debug);
+        }
+    }
+
+    #[test]
+    fn test_name_entries() {
+        let body = name_body(&["a", "b"]);
+
+        let (response_id, mut entries) = Response::name_entries(&body).unwrap();
+        assert_eq!(response_id, 0x1234);
+        assert_eq!(entries.remaining(), 2);
+        assert_eq!(&*entries.next().unwrap().unwrap().filename, Path::new("a"));
+        assert_eq!(entries.remaining(), 1);
+        assert_eq!(&*entries.next().unwrap().unwrap().filename, Path::new("b"));
+        assert!(entries.next().is_none());
+
+        // Truncated entry is reported once
+        let (_, mut entries) = Response::name_entries(&body[..body.len() - 1]).unwrap();
+        entries.next().unwrap().unwrap();
+        entries.next().unwrap().unwrap_err();
+        assert!(entries.next().is_none());
+
+        Response::name_entries(&status_body(constants::SSH_FX_OK, None)).unwrap_err();
+    }
+
+    #[test]
+    fn test_handle() {
+        let response_body = |len: usize| {
+            let mut body = vec![constants::SSH_FXP_HANDLE];
+            body.extend_from_slice(&0x1234_u32.to_be_bytes());
+            push_bytes(&mut body, &vec![0xff; len]);
+            body
+        };
+
+        match Response::decode_body(&response_body(crate::Handle::MAX_LEN))
+            .unwrap()
+            .response_inner
+        {
+            ResponseInner::Handle(handle) => {
+                assert_eq!(handle.into_inner(), &[0xff; crate::Handle::MAX_LEN][..])
+            }
+            response_inner => panic!("Unexpected response {:#?}", response_inner),
+        }
+
+        let err = Response::decode_body(&response_body(crate::Handle::MAX_LEN + 1)).unwrap_err();
+        assert!(err.to_string().contains("exceeds the limit of 256 bytes"));
+
+        // Length is checked before reading in the handle
+        let mut body = response_body(0);
+        body[5..9].copy_from_slice(&u32::MAX.to_be_bytes());
+        Response::decode_body(&body).unwrap_err();
+    }
+
+    #[test]
+    fn test_handle_with_attrs() {
+        let mut attrs = FileAttrs::new();
+        attrs.set_size(1024);
+
+        let mut body = vec![constants::SSH_FXP_HANDLE];
+        body.extend_from_slice(&0x1234_u32.to_be_bytes());
+        push_bytes(&mut body, b"1234");
+        body.extend_from_slice(&ssh_format::to_bytes(&attrs).unwrap()[4..]);
+
+        match Response::decode_body(&body).unwrap().response_inner {
+            ResponseInner::HandleWithAttrs(handle, response_attrs) => {
+                assert_eq!(handle.into_inner(), &b"1234"[..]);
+                assert_eq!(response_attrs, attrs);
+            }
+            response_inner => panic!("Unexpected response {:#?}", response_inner),
+        }
+    }
+
+    #[test]
+    fn test_deserialize_handle() {
+        let mut body = vec![constants::SSH_FXP_HANDLE];
+        body.extend_from_slice(&0x1234_u32.to_be_bytes());
+        push_bytes(&mut body, b"1234");
+
+        let assert_handle = |response: &Response<'_>| match &response.response_inner {
+            ResponseInner::Handle(handle) => assert_eq!(handle.into_inner(), &b"1234"[..]),
+            response_inner => panic!("Unexpected response {:#?}", response_inner),
+        };
+
+        let (response, rest) = ssh_format::from_bytes::<Response>(&body).unwrap();
+        assert!(rest.is_empty());
+        assert_handle(&response);
+
+        // The status following the handle must not be read as attributes
+        let mut stream = body.clone();
+        stream.extend_from_slice(&status_body(constants::SSH_FX_OK, Some(("", ""))));
+
+        let de = &mut ssh_format::Deserializer::from_bytes(&stream);
+        assert_handle(&Response::deserialize(&mut *de).unwrap());
+        assert!(matches!(
+            Response::deserialize(&mut *de).unwrap().response_inner,
+            ResponseInner::Status {
+                status_code: StatusCode::Success,
+                ..
+            }
+        ));
+        assert!(!de.has_remaining_data());
+    }
+
+    #[test]
+    fn test_disk_usage() {
+        let statvfs = StatVfs {
+            f_bsize: 4096,
+            f_frsize: 1024,
+            f_blocks: 100,
+            f_bfree: 40,
+            f_bavail: 30,
+            f_files: 0,
+            f_ffree: 0,
+            f_favail: 0,
+            f_sid: 0,
+            f_flag: 0,
+            f_namemax: 255,
+        };
+        assert_eq!(
+            DiskUsage::from(statvfs),
+            DiskUsage {
+                total_bytes: 102400,
+                available_bytes: 30720,
+                used_bytes: 61440,
+            }
+        );
+
+        let mut payload = Vec::new();
+        for bytes in [102400_u64, 40960, 92160, 30720] {
+            payload.extend_from_slice(&bytes.to_be_bytes());
+        }
+        payload.extend_from_slice(&1024_u32.to_be_bytes());
+
+        let (space, rest) = ssh_format::from_bytes::<SpaceAvailable>(&payload).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(space.bytes_per_allocation_unit, 1024);
+        assert_eq!(
+            DiskUsage::from(space),
+            DiskUsage {
+                total_bytes: 102400,
+                available_bytes: 30720,
+                used_bytes: 61440,
+            }
+        );
+
+        ssh_format::from_bytes::<SpaceAvailable>(&payload[..32]).unwrap_err();
+    }
+
+    #[test]
+    fn test_check_read_len() {
+        assert_eq!(Response::check_read_len(10, 10).unwrap(), ReadLen::Full);
+        assert_eq!(Response::check_read_len(10, 3).unwrap(), ReadLen::Short(3));
+        assert_eq!(Response::check_read_len(10, 0).unwrap(), ReadLen::Short(0));
+        Response::check_read_len(10, 11).unwrap_err();
+    }
+
+    #[test]
+    fn test_decode_data() {
+        let mut body = vec![constants::SSH_FXP_DATA];
+        body.extend_from_slice(&12_u32.to_be_bytes());
+        push_bytes(&mut body, b"abc");
+
+        let (response_id, data) = Response::decode_data(&body).unwrap();
+        assert_eq!(response_id, 12);
+        assert_eq!(data, b"abc");
+        assert_eq!(data.as_ptr(), body[9..].as_ptr());
+
+        let de = &mut ssh_format::Deserializer::from_bytes(&body[5..]);
+        let data = Response::deserialize_data(&mut *de).unwrap();
+        assert!(matches!(data, Cow::Borrowed(b"abc")));
+
+        body.push(0);
+        Response::decode_data(&body).unwrap_err();
+
+        body[0] = constants::SSH_FXP_ATTRS;
+        Response::decode_data(&body[..body.len() - 1]).unwrap_err();
+    }
+
+    #[test]
+    fn test_data_and_extended_reply() {
+        let mut body = vec![constants::SSH_FXP_DATA];
+        body.extend_from_slice(&12_u32.to_be_bytes());
+        push_bytes(&mut body, b"abc");
+
+        let response = Response::decode_body(&body).unwrap();
+        assert_eq!(response.response_id, 12);
+        match &response.response_inner {
+            ResponseInner::Data(Cow::Borrowed(data)) => {
+                assert_eq!(data.as_ptr(), body[9..].as_ptr())
+            }
+            response => panic!("Unexpected response {:#?}", response),
+        }
+        assert_eq!(ssh_format::to_bytes(&response).unwrap()[4..], body);
+
+        body.push(0);
+        Response::decode_body(&body).unwrap_err();
+
+        let mut body = vec![constants::SSH_FXP_EXTENDED_REPLY];
+        body.extend_from_slice(&13_u32.to_be_bytes());
+        body.extend_from_slice(b"payload");
+
+        let response = Response::decode_body(&body).unwrap();
+        assert_eq!(response.response_id, 13);
+        match &response.response_inner {
+            ResponseInner::ExtendedReply(Cow::Borrowed(payload)) => {
+                assert_eq!(payload.as_ptr(), body[5..].as_ptr())
+            }
+            response => panic!("Unexpected response {:#?}", response),
+        }
+        assert_eq!(ssh_format::to_bytes(&response).unwrap()[4..], body);
+
+        // The payload runs till the end of the packet, which is unknown to
+        // Deserialize, so it is rejected instead of swallowing the
+        // following packets.
+        let err = ssh_format::from_bytes::<Response>(&body).unwrap_err();
+        assert!(err.to_string().contains("Invalid packet type"), "{}", err);
+
+        Response::decode_body(&body[..3]).unwrap_err();
+    }
+
+    #[test]
+    fn test_limits_display() {
+        assert_eq!(
+            Limits::DEFAULT.to_string(),
+            "read 32 KiB, write 32 KiB, packet 256 KiB, handles unlimited"
+        );
+
+        let limits = Limits {
+            packet_len: 34000,
+            read_len: 2 << 30,
+            write_len: 0,
+            open_handles: 1024,
+        };
+        assert_eq!(
+            limits.to_string(),
+            "read 2 GiB, write unlimited, packet 34000 B, handles 1024"
+        );
+    }
+
+    #[test]
+    fn test_limits_default() {
+        let limits = Limits::default();
+
+        assert_eq!(limits, Limits::DEFAULT);
+        assert_eq!(limits.read_len, 32768);
+        assert_eq!(limits.write_len, 32768);
+        assert!(limits.read_len < limits.packet_len);
+    }
+
+    #[test]
+    fn test_parse_extended_reply() {
+        let mut body = vec![constants::SSH_FXP_EXTENDED_REPLY];
+        body.extend_from_slice(&0x1234_u32.to_be_bytes());
+        for limit in [1_u64, 2, 3, 4] {
+            body.extend_from_slice(&limit.to_be_bytes());
+        }
+
+        let (response_id, limits) = Response::parse_extended_reply::<Limits>(&body).unwrap();
+        assert_eq!(response_id, 0x1234);
+        assert_eq!(
+            limits,
+            Limits {
+                packet_len: 1,
+                read_len: 2,
+                write_len: 3,
+                open_handles: 4,
+            }
+        );
+
+        Response::parse_extended_reply::<StatVfs>(&body).unwrap_err();
+
+        body.push(0);
+        Response::parse_extended_reply::<Limits>(&body).unwrap_err();
+
+        body[0] = constants::SSH_FXP_DATA;
+        Response::parse_extended_reply::<Limits>(&body[..body.len() - 1]).unwrap_err();
+    }
+
+    #[test]
+    fn test_status_code_display() {
+        let status_codes = [
+            (StatusCode::Success, "success"),
+            (StatusCode::Eof, "unexpected end of file"),
+            (StatusCode::Failure(ErrorCode::NoSuchFile), "no such file"),
+            (
+                StatusCode::Failure(ErrorCode::PermDenied),
+                "permission denied",
+            ),
+            (
+                StatusCode::Failure(ErrorCode::OpUnsupported),
+                "operation unsupported",
+            ),
+            (
+                StatusCode::Failure(ErrorCode::Unknown(100)),
+                "unknown error code 100",
+            ),
+        ];
+
+        for (status_code, s) in status_codes {
+            assert_eq!(status_code.to_string(), s);
+        }
+
+        let err: Box<dyn std::error::Error> = Box::new(StatusCode::Eof);
+        assert_eq!(err.to_string(), "unexpected end of file");
+    }
+
+    #[test]
+    fn test_status_into_io_error() {
+        let err = io::Error::from(StatusCode::Failure(ErrorCode::NoSuchFile));
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(err.to_string(), "no such file");
+
+        let err = StatusCode::Failure(ErrorCode::PermDenied)
+            .into_io_error(&ErrMsg::new("Permission denied", "en"));
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(err.to_string(), "Permission denied");
+
+        let kinds = [
+            (ErrorCode::OpUnsupported, io::ErrorKind::Unsupported),
+            (ErrorCode::Failure, io::ErrorKind::Other),
+            (ErrorCode::BadMessage, io::ErrorKind::Other),
+            (ErrorCode::Unknown(100), io::ErrorKind::Other),
+        ];
+        for (err_code, kind) in kinds {
+            assert_eq!(io::Error::from(StatusCode::Failure(err_code)).kind(), kind);
+        }
+
+        let err = io::Error::from(StatusCode::Eof);
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_status_from_io_error() {
+        let kinds = [
+            (io::ErrorKind::NotFound, constants::SSH_FX_NO_SUCH_FILE),
+            (
+                io::ErrorKind::PermissionDenied,
+                constants::SSH_FX_PERMISSION_DENIED,
+            ),
+            (io::ErrorKind::Unsupported, constants::SSH_FX_OP_UNSUPPORTED),
+            (io::ErrorKind::InvalidInput, constants::SSH_FX_BAD_MESSAGE),
+            (io::ErrorKind::InvalidData, constants::SSH_FX_BAD_MESSAGE),
+            (io::ErrorKind::UnexpectedEof, constants::SSH_FX_EOF),
+            (io::ErrorKind::Other, constants::SSH_FX_FAILURE),
+            (io::ErrorKind::TimedOut, constants::SSH_FX_FAILURE),
+        ];
+
+        for (kind, code) in kinds {
+            let err = io::Error::new(kind, "boom");
+            let bytes = ssh_format::to_bytes(&Response {
+                response_id: 0x1234,
+                response_inner: ResponseInner::status_from_io_error(&err),
+            })
+            .unwrap();
+            assert_eq!(bytes[4..], status_body(code, Some(("boom", "en"))));
+
+            let response = Response::decode_body(&bytes[4..]).unwrap();
+            assert_eq!(response.response_id, 0x1234);
+            match &response.response_inner {
+                ResponseInner::Status {
+                    status_code,
+                    err_msg,
+                } => {
+                    assert_eq!(
+                        ssh_format::to_bytes(status_code).unwrap()[4..],
+                        code.to_be_bytes()
+                    );
+                    assert_eq!(err_msg.get(), ("boom", "en"));
+                }
+                response_inner => panic!("Unexpected response {:#?}", response_inner),
+            }
+        }
+    }
+
+    #[test]
+    fn test_serialize_response() {
+        let mut attrs = FileAttrs::new();
+        attrs.set_size(100);
+
+        let responses = [
+            ResponseInner::Status {
+                status_code: StatusCode::Failure(ErrorCode::NoSuchFile),
+                err_msg: ErrMsg::new("No such file", "en"),
+            },
+            ResponseInner::Status {
+                status_code: StatusCode::Failure(ErrorCode::Unknown(1000)),
+                err_msg: ErrMsg::default(),
+            },
+            ResponseInner::Handle(HandleOwned::new(*b"1234")),
+            ResponseInner::HandleWithAttrs(HandleOwned::new(*b"1234"), attrs.clone()),
+            ResponseInner::Name(
+                vec![NameEntry {
+                    filename: Path::new("a").into(),
+                    longname: (*b"-rw-r--r-- a").into(),
+                    attrs: attrs.clone(),
+                }]
+                .into(),
+            ),
+            ResponseInner::Attrs(attrs),
+        ];
+
+        for response_inner in responses {
+            let bytes = ssh_format::to_bytes(&Response {
+                response_id: 0x1234,
+                response_inner,
+            })
+            .unwrap();
+
+            let response = Response::decode_packet(&bytes).unwrap();
+            assert_eq!(response.response_id, 0x1234);
+            assert_eq!(ssh_format::to_bytes(&response).unwrap(), bytes);
+        }
+
+        let bytes = ssh_format::to_bytes(&Response {
+            response_id: 0x1234,
+            response_inner: ResponseInner::Status {
+                status_code: StatusCode::Eof,
+                err_msg: ErrMsg::new("EOF", "en"),
+            },
+        })
+        .unwrap();
+        assert_eq!(
+            bytes[4..],
+            status_body(constants::SSH_FX_EOF, Some(("EOF", "en")))
+        );
+    }
+
+    #[test]
+    fn test_serialize_status_code_and_err_msg() {
+        let status_code = StatusCode::Failure(ErrorCode::QuotaExceeded);
+        let bytes = ssh_format::to_bytes(&status_code).unwrap();
+        assert_eq!(bytes[4..], constants::SSH_FX_QUOTA_EXCEEDED.to_be_bytes());
+
+        let (status_code, _) = ssh_format::from_bytes::<StatusCode>(&bytes[4..]).unwrap();
+        assert!(matches!(
+            status_code,
+            StatusCode::Failure(ErrorCode::QuotaExceeded)
+        ));
+
+        let bytes = ssh_format::to_bytes(&ErrMsg::new("msg", "en")).unwrap();
+        let (err_msg, _) = ssh_format::from_bytes::<ErrMsg>(&bytes[4..]).unwrap();
+        assert_eq!(err_msg.get(), ("msg", "en"));
+        assert_eq!(bytes[4..], *b"\0\0\0\x03msg\0\0\0\x02en");
+    }
+
+    #[test]
+    fn test_status_message() {
+        let body = status_body(constants::SSH_FX_OK, Some(("Renamed", "en")));
+        let response = Response::decode_body(&body).unwrap();
+        assert_eq!(
+            response.response_inner.status_message(),
+            Some(("Renamed", "en"))
+        );
+
+        let body = status_body(constants::SSH_FX_FAILURE, None);
+        let response = Response::decode_body(&body).unwrap();
+        assert_eq!(response.response_inner.status_message(), Some(("", "")));
+
+        let response_inner = ResponseInner::Attrs(FileAttrs::new());
+        assert_eq!(response_inner.status_message(), None);
+    }
+
+    #[test]
+    fn test_status_unknown_code() {
+        let unknown_code = |code| match Response::decode_body(&status_body(code, None))
+            .unwrap()
+            .response_inner
+        {
+            ResponseInner::Status { status_code, .. } => status_code.unknown_code(),
+            response_inner => panic!("Unexpected response {:#?}", response_inner),
+        };
+
+        assert_eq!(unknown_code(constants::SSH_FX_FAILURE), None);
+        assert_eq!(unknown_code(1000), Some(1000));
+    }
+
+    #[test]
+    fn test_status_v6_error_codes() {
+        let err_code = |code| match Response::decode_body(&status_body(code, None))
+            .unwrap()
+            .response_inner
+        {
+            ResponseInner::Status {
+                status_code: StatusCode::Failure(err_code),
+                ..
+            } => err_code,
+            response_inner => panic!("Unexpected response {:#?}", response_inner),
+        };
+
+        assert!(matches!(
+            err_code(constants::SSH_FX_INVALID_HANDLE),
+            ErrorCode::InvalidHandle
+        ));
+        assert!(matches!(
+            err_code(constants::SSH_FX_NO_SUCH_PATH),
+            ErrorCode::NoSuchPath
+        ));
+        assert!(matches!(
+            err_code(constants::SSH_FX_FILE_ALREADY_EXISTS),
+            ErrorCode::FileAlreadyExists
+        ));
+        assert!(matches!(
+            err_code(constants::SSH_FX_WRITE_PROTECT),
+            ErrorCode::WriteProtect
+        ));
+        assert!(matches!(
+            err_code(constants::SSH_FX_NO_SPACE_ON_FILESYSTEM),
+            ErrorCode::NoSpaceOnFilesystem
+        ));
+        assert!(matches!(
+            err_code(constants::SSH_FX_QUOTA_EXCEEDED),
+            ErrorCode::QuotaExceeded
+        ));
+
+        // SSH_FX_NO_MEDIA
+        assert!(matches!(err_code(13), ErrorCode::Unknown(13)));
+    }
+
+    #[test]
+    fn test_status_with_err_msg() {
+        let body = status_body(constants::SSH_FX_NO_SUCH_FILE, Some(("No such file", "en")));
+        let (response, trailing) = ssh_format::from_bytes::<Response>(&body).unwrap();
+
+        assert!(trailing.is_empty());
+        assert_eq!(response.response_id, 0x1234);
+        match response.response_inner {
+            ResponseInner::Status {
+                status_code: StatusCode::Failure(ErrorCode::NoSuchFile),
+                err_msg,
+            } => assert_eq!(err_msg.get(), ("No such file", "en")),
+            response_inner => panic!("Unexpected response {:#?}", response_inner),
+        }
+    }
+
+    #[test]
+    fn test_decode_body_and_packet() {
+        let body = status_body(constants::SSH_FX_OK, Some(("", "")));
+
+        let mut packet = (body.len() as u32).to_be_bytes().to_vec();
+        packet.extend_from_slice(&body);
+
+        for response in [
+            Response::decode_body(&body).unwrap(),
+            Response::decode_packet(&packet).unwrap(),
+        ] {
+            assert_eq!(response.response_id, 0x1234);
+            assert!(matches!(
+                response.response_inner,
+                ResponseInner::Status {
+                    status_code: StatusCode::Success,
+                    ..
+                }
+            ));
+        }
+
+        // Mixing them up is detected instead of silently misparsing
+        Response::decode_body(&packet).unwrap_err();
+        Response::decode_packet(&body).unwrap_err();
+    }
+
+    #[test]
+    fn test_status_without_err_msg() {
+        let body = status_body(constants::SSH_FX_PERMISSION_DENIED, None);
+        let response = Response::decode_body(&body).unwrap();
+
+        assert_eq!(response.response_id, 0x1234);
+        match response.response_inner {
+            ResponseInner::Status {
+                status_code: StatusCode::Failure(ErrorCode::PermDenied),
+                err_msg,
+            } => assert_eq!(err_msg.get(), ("", "")),
+            response_inner => panic!("Unexpected response {:#?}", response_inner),
+        }
+
+        // Cannot tell the missing err message apart from the next packet
+        ssh_format::from_bytes::<Response>(&body).unwrap_err();
+
+        let body = status_body(constants::SSH_FX_PERMISSION_DENIED, Some(("denied", "en")));
+        let (response, rest) = ssh_format::from_bytes::<Response>(&body).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(
+            response.response_inner.status_message(),
+            Some(("denied", "en"))
+        );
+    }
+}
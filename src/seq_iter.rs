@@ -1,9 +1,9 @@
 #![forbid(unsafe_code)]
 
-use std::marker::PhantomData;
+use std::{borrow::Cow, fmt, iter::FusedIterator, marker::PhantomData};
 
 use serde::{
-    de::{Error, SeqAccess},
+    de::{DeserializeSeed, Deserializer, Error, SeqAccess, Visitor},
     Deserialize,
 };
 
@@ -22,4 +22,199 @@ impl<'de, V: SeqAccess<'de>> SeqIter<'de, V> {
         self.0 += 1;
         res
     }
+
+    /// Return `Ok(None)` if the sequence ends.
+    ///
+    /// Sequences of ssh_format never end, so only sequences visited via
+    /// [`from_packet_body`] end once there is no more data left, while
+    /// others return an error.
+    pub(crate) fn get_next_optional<T: Deserialize<'de>>(&mut self) -> Result<Option<T>, V::Error> {
+        let res = self.1.next_element()?;
+        self.0 += 1;
+        Ok(res)
+    }
+
+    /// Read in a byte string, which is a `u32` length followed by the bytes.
+    ///
+    /// Return an error if the length is larger than `max_len`.
+    ///
+    /// The byte string is read in one go and borrowed from the input if
+    /// possible, so it is only copied once it is within the limit.
+    pub(crate) fn get_next_bytes_capped(&mut self, max_len: usize) -> Result<Vec<u8>, V::Error> {
+        let bytes = self.get_next::<CowBytes<'de>>()?.0;
+
+        if bytes.len() > max_len {
+            return Err(Error::custom(format_args!(
+                "byte string of {} bytes exceeds the limit of {} bytes",
+                bytes.len(),
+                max_len
+            )));
+        }
+
+        Ok(bytes.into_owned())
+    }
+
+    /// Same as [`SeqIter::get_next`], except that the error returned says
+    /// that `name` is truncated and is expected to have `fields_cnt` fields.
+    ///
+    /// Must only be used for integers, which can only fail to decode if
+    /// there is not enough data left.
+    pub(crate) fn get_next_or_truncated<T: Deserialize<'de>>(
+        &mut self,
+        name: &str,
+        fields_cnt: usize,
+    ) -> Result<T, V::Error> {
+        let index = self.0;
+
+        match self.get_next_optional() {
+            Ok(Some(value)) => Ok(value),
+            Ok(None) | Err(_) => Err(Error::custom(format_args!(
+                "truncated {}, expected {} fields but only got {}",
+                name, fields_cnt, index
+            ))),
+        }
+    }
+}
+
+/// Byte string borrowed from the input if possible.
+struct CowBytes<'de>(Cow<'de, [u8]>);
+
+impl<'de> Deserialize<'de> for CowBytes<'de> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer
+            .deserialize_bytes(CowBytesVisitor)
+            .map(CowBytes)
+    }
+}
+
+struct CowBytesVisitor;
+
+impl<'de> Visitor<'de> for CowBytesVisitor {
+    type Value = Cow<'de, [u8]>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("byte string")
+    }
+
+    fn visit_borrowed_bytes<E: Error>(self, v: &'de [u8]) -> Result<Self::Value, E> {
+        Ok(Cow::Borrowed(v))
+    }
+
+    fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(Cow::Owned(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E: Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(Cow::Owned(v))
+    }
+}
+
+/// Decode `seed` from `body`, which must contain exactly one packet without
+/// the 4-byte length header.
+///
+/// Unlike [`ssh_format::from_bytes`], the outermost tuple of `seed` ends
+/// once there is no data left in `body`, so that trailing optional fields
+/// can be read with [`SeqIter::get_next_optional`].
+///
+/// Pass `PhantomData::<T>` to decode `T: Deserialize`.
+pub(crate) fn from_packet_body<'de, S: DeserializeSeed<'de>>(
+    body: &'de [u8],
+    seed: S,
+    name: &str,
+) -> ssh_format::Result<S::Value> {
+    let de = &mut ssh_format::Deserializer::from_bytes(body);
+
+    let value = seed.deserialize(PacketDeserializer(&mut *de))?;
+
+    if de.has_remaining_data() {
+        return Err(ssh_format::Error::custom(format_args!(
+            "Trailing bytes after the {}",
+            name
+        )));
+    }
+
+    Ok(value)
+}
+
+/// Forward to the inner deserializer, except that the tuple is visited
+/// with [`PacketAccess`].
+pub(crate) struct PacketDeserializer<'a, 'de, It>(
+    pub(crate) &'a mut ssh_format::Deserializer<'de, It>,
+);
+
+macro_rules! forward {
+    ($( $name:ident ( $($arg:ident : $ty:ty),* ) ),* $(,)?) => {
+        $(
+            fn $name<V: Visitor<'de>>(self, $($arg: $ty,)* visitor: V) -> ssh_format::Result<V::Value> {
+                self.0.$name($($arg,)* visitor)
+            }
+        )*
+    };
+}
+
+impl<'a, 'de, It> Deserializer<'de> for PacketDeserializer<'a, 'de, It>
+where
+    It: FusedIterator + Iterator<Item = &'de [u8]>,
+{
+    type Error = ssh_format::Error;
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> ssh_format::Result<V::Value> {
+        visitor.visit_seq(PacketAccess(self.0))
+    }
+
+    forward!(
+        deserialize_any(),
+        deserialize_bool(),
+        deserialize_i8(),
+        deserialize_i16(),
+        deserialize_i32(),
+        deserialize_i64(),
+        deserialize_u8(),
+        deserialize_u16(),
+        deserialize_u32(),
+        deserialize_u64(),
+        deserialize_f32(),
+        deserialize_f64(),
+        deserialize_char(),
+        deserialize_str(),
+        deserialize_string(),
+        deserialize_bytes(),
+        deserialize_byte_buf(),
+        deserialize_option(),
+        deserialize_unit(),
+        deserialize_unit_struct(name: &'static str),
+        deserialize_newtype_struct(name: &'static str),
+        deserialize_seq(),
+        deserialize_tuple_struct(name: &'static str, len: usize),
+        deserialize_map(),
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]),
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]),
+        deserialize_identifier(),
+        deserialize_ignored_any(),
+    );
+}
+
+/// Sequence that ends once there is no data left.
+struct PacketAccess<'a, 'de, It>(&'a mut ssh_format::Deserializer<'de, It>);
+
+impl<'a, 'de, It> SeqAccess<'de> for PacketAccess<'a, 'de, It>
+where
+    It: FusedIterator + Iterator<Item = &'de [u8]>,
+{
+    type Error = ssh_format::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> ssh_format::Result<Option<T::Value>> {
+        if self.0.has_remaining_data() {
+            seed.deserialize(&mut *self.0).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
 }